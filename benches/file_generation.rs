@@ -1,4 +1,5 @@
 use assert_fs::{fixture, prelude::*};
+use fastrand::Rng;
 use std::{
     env, fmt,
     path::{Path, PathBuf},
@@ -10,10 +11,22 @@ const MEDIUM_FILE: &str = "medium_file.txt";
 const HEAVY_FILE: &str = "heavy_file.txt";
 const TEST_FILE_DIRECTORY: &str = "test_files";
 
+/// Share of light, medium and heavy files for `FileType::Mixed`, most files in a repository are small.
+const MIXED_WEIGHTS: [(FileType, u32); 3] = [
+    (FileType::Light, 70),
+    (FileType::Medium, 25),
+    (FileType::Heavy, 5),
+];
+/// Directories a repository has but a search would usually skip, generated with `ignored_dirs`.
+const IGNORED_DIRS: [&str; 3] = [".git/objects", "target/debug", "node_modules/package"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum FileType {
     Light,
     Medium,
     Heavy,
+    /// Each file is one of the others, see `MIXED_WEIGHTS`
+    Mixed,
 }
 
 impl fmt::Display for FileType {
@@ -22,6 +35,7 @@ impl fmt::Display for FileType {
             FileType::Light => write!(f, "Light"),
             FileType::Medium => write!(f, "Medium"),
             FileType::Heavy => write!(f, "Heavy"),
+            FileType::Mixed => write!(f, "Mixed"),
         }
     }
 }
@@ -34,8 +48,9 @@ impl FromStr for FileType {
             "light" => Ok(FileType::Light),
             "medium" => Ok(FileType::Medium),
             "heavy" => Ok(FileType::Heavy),
+            "mixed" => Ok(FileType::Mixed),
             _ => Err(
-                "Not an expected conversion string. Required to be either [light, medium or heavy]"
+                "Not an expected conversion string. Required to be either [light, medium, heavy or mixed]"
                     .to_string(),
             ),
         }
@@ -48,7 +63,25 @@ impl FileType {
             FileType::Light => LIGHT_FILE,
             FileType::Medium => MEDIUM_FILE,
             FileType::Heavy => HEAVY_FILE,
+            FileType::Mixed => panic!("A mixed file is one of the others, see 'FileType::pick'"),
+        }
+    }
+
+    /// The file type to generate next, only random when mixed.
+    fn pick(&self, rng: &mut Rng) -> FileType {
+        if *self != FileType::Mixed {
+            return *self;
         }
+
+        let total: u32 = MIXED_WEIGHTS.iter().map(|(_, weight)| weight).sum();
+        let mut roll = rng.u32(0..total);
+        for (file_type, weight) in MIXED_WEIGHTS {
+            if roll < weight {
+                return file_type;
+            }
+            roll -= weight;
+        }
+        unreachable!("'roll' is below the total of the weights")
     }
 
     fn get_path(&self) -> PathBuf {
@@ -66,27 +99,135 @@ impl FileType {
     }
 }
 
-pub fn create_files(
-    temp: &fixture::TempDir,
-    file_to_duplicate: FileType,
-    num_of_nested_dirs: usize,
-    num_of_files_to_create: usize,
-) -> &Path {
-    let _ = num_of_nested_dirs;
-    let filename = file_to_duplicate.get_filename();
-    let filename_path = file_to_duplicate.get_path();
+/**
+ * The shape of a generated tree, so benchmarks search something closer to a real repository than a
+ * single directory of identical files.
+ *
+ * `depth` levels of `fan_out` subdirectories are created and the files spread across them at random.
+ * The same seed always produces the same tree.
+ */
+#[derive(Clone, Debug)]
+pub struct TreeSpec {
+    pub num_of_files: usize,
+    pub file_type: FileType,
+    pub depth: usize,
+    pub fan_out: usize,
+    /// Random bytes (including NULs), which are still searched as there's no binary detection
+    pub num_of_binary_files: usize,
+    /// Adds `.git`, `target` and `node_modules` (listed in a `.gitignore`) with a tenth as many files again.
+    /// Nothing skips them yet, so they're searched along with everything else
+    pub ignored_dirs: bool,
+    pub seed: u64,
+}
+
+impl Default for TreeSpec {
+    fn default() -> Self {
+        TreeSpec {
+            num_of_files: 10,
+            file_type: FileType::Light,
+            depth: 0,
+            fan_out: 0,
+            num_of_binary_files: 0,
+            ignored_dirs: false,
+            seed: 0,
+        }
+    }
+}
+
+impl TreeSpec {
+    /// The root and every nested directory, relative to the root.
+    pub fn directories(&self) -> Vec<PathBuf> {
+        let mut directories = vec![PathBuf::new()];
+        let mut level = vec![PathBuf::new()];
+        for depth in 0..self.depth {
+            level = level
+                .iter()
+                .flat_map(|parent| {
+                    (0..self.fan_out).map(move |i| parent.join(format!("dir_{depth}_{i}")))
+                })
+                .collect();
+            directories.extend(level.iter().cloned());
+        }
+        directories
+    }
+}
 
+/// What a generated file holds.
+enum Content {
+    /// A copy of one of the test files, read once however many copies there are
+    Copy(FileType),
+    Bytes(Vec<u8>),
+}
+
+/// Every file to create, relative to the root. Decided up front so the files can be written in
+/// parallel while the tree stays the same for a seed.
+fn plan_files(spec: &TreeSpec) -> Vec<(PathBuf, Content)> {
+    let mut rng = Rng::with_seed(spec.seed);
+    let directories = spec.directories();
+
+    let mut files = Vec::new();
+    for idx in 1..=spec.num_of_files {
+        let file_type = spec.file_type.pick(&mut rng);
+        let directory = &directories[rng.usize(0..directories.len())];
+        files.push((
+            directory.join(format!("{}_{}", idx, file_type.get_filename())),
+            Content::Copy(file_type),
+        ));
+    }
+
+    for idx in 1..=spec.num_of_binary_files {
+        let directory = &directories[rng.usize(0..directories.len())];
+        let mut content = vec![0; rng.usize(4 * 1024..64 * 1024)];
+        rng.fill(&mut content);
+        files.push((
+            directory.join(format!("{idx}.bin")),
+            Content::Bytes(content),
+        ));
+    }
+
+    if spec.ignored_dirs {
+        files.push((
+            PathBuf::from(".gitignore"),
+            Content::Bytes(b"/target\n/node_modules\n".to_vec()),
+        ));
+        for idx in 1..=spec.num_of_files.div_ceil(10) {
+            let directory = IGNORED_DIRS[idx % IGNORED_DIRS.len()];
+            let file_type = spec.file_type.pick(&mut rng);
+            files.push((
+                Path::new(directory).join(format!("{}_{}", idx, file_type.get_filename())),
+                Content::Copy(file_type),
+            ));
+        }
+    }
+
+    files
+}
+
+pub fn create_files<'a>(temp: &'a fixture::TempDir, spec: &TreeSpec) -> &'a Path {
     // Read content once and reuse for all files
-    let content = std::fs::read(&filename_path)
-        .unwrap_or_else(|_| panic!("Failed to read {}", filename_path.display()));
+    let test_files: Vec<(FileType, Vec<u8>)> = [FileType::Light, FileType::Medium, FileType::Heavy]
+        .into_iter()
+        .map(|file_type| {
+            let path = file_type.get_path();
+            let content = std::fs::read(&path)
+                .unwrap_or_else(|_| panic!("Failed to read {}", path.display()));
+            (file_type, content)
+        })
+        .collect();
 
     use rayon::prelude::*;
-    (1..=num_of_files_to_create)
+    plan_files(spec)
         .into_par_iter()
-        .for_each(|idx| {
-            temp.child(format!("{}_{}", idx, filename))
-                .write_binary(&content)
-                .unwrap();
+        .for_each(|(path, content)| {
+            let content = match &content {
+                Content::Copy(file_type) => test_files
+                    .iter()
+                    .find(|(t, _)| t == file_type)
+                    .map(|(_, content)| content)
+                    .unwrap(),
+                Content::Bytes(bytes) => bytes,
+            };
+            temp.child(path).write_binary(content).unwrap();
         });
 
     temp.path()
@@ -103,49 +244,3 @@ fn find_project_root() -> Option<PathBuf> {
         }
     }
 }
-
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn test_setup() {
-        use super::*;
-        use walkdir::WalkDir;
-
-        let num_of_files_to_duplicate = 5;
-        let num_of_directories_to_duplicate = 1;
-
-        let temp_dir: fixture::TempDir = assert_fs::TempDir::new().unwrap();
-        let p = create_files(
-            &temp_dir,
-            FileType::Light,
-            num_of_directories_to_duplicate,
-            num_of_files_to_duplicate,
-        );
-        assert!(
-            p.is_dir(),
-            "This should return the directory where the test files are available"
-        );
-
-        // purely for testing.
-        let available_files = WalkDir::new(p)
-            .into_iter()
-            .filter(|entry| match entry {
-                Err(err) => {
-                    panic!("{}", err);
-                }
-                Ok(entry) => !entry.file_type().is_dir(),
-            })
-            .inspect(|entry| {
-                println!("Entry is: {:?}", entry);
-            })
-            .count();
-
-        assert_eq!(
-            available_files,
-            num_of_files_to_duplicate * num_of_directories_to_duplicate,
-            "We should find the number of files including the nested directories."
-        );
-
-        temp_dir.close().unwrap();
-    }
-}
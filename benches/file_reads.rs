@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use assert_fs::TempDir;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+use rustcomb::mmap::MmapChoice;
+
+/// Roughly a source tree, thousands of files of a few KB each.
+const NUM_OF_FILES: usize = 2000;
+const FILE_LEN: usize = 4 * 1024;
+
+fn small_files(temp_dir: &TempDir) -> Vec<PathBuf> {
+    let heavy = std::fs::read(Path::new("test_files").join("heavy_file.txt"))
+        .expect("Expect to find 'test_files/heavy_file.txt'");
+
+    (0..NUM_OF_FILES)
+        .map(|i| {
+            let path = temp_dir.path().join(format!("{i}.txt"));
+            let start = (i * FILE_LEN) % (heavy.len() - FILE_LEN);
+            std::fs::write(&path, &heavy[start..start + FILE_LEN]).unwrap();
+            path
+        })
+        .collect()
+}
+
+/// Reading every file whole, mapped, into a buffer and (with the `io_uring` feature) batched with io_uring.
+fn bench_file_reads(c: &mut Criterion) {
+    let temp_dir = TempDir::new().unwrap();
+    let paths = small_files(&temp_dir);
+
+    let mut group = c.benchmark_group("small file reads");
+    group.throughput(Throughput::Bytes((NUM_OF_FILES * FILE_LEN) as u64));
+
+    for (name, mmap) in [
+        ("mmap", MmapChoice::Always),
+        ("buffered", MmapChoice::Never),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                paths
+                    .iter()
+                    .map(|path| mmap.read(path).unwrap().len())
+                    .sum::<usize>()
+            })
+        });
+    }
+
+    #[cfg(all(feature = "io_uring", target_os = "linux"))]
+    {
+        use rustcomb::uring::{BATCH_SIZE, UringReader};
+
+        let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+        match UringReader::new(BATCH_SIZE as u32) {
+            Ok(mut reader) => {
+                group.bench_function("io_uring", |b| {
+                    b.iter(|| {
+                        reader
+                            .read_files(&paths)
+                            .into_iter()
+                            .map(|contents| contents.unwrap().len())
+                            .sum::<usize>()
+                    })
+                });
+            }
+            Err(err) => eprintln!("Skipping io_uring, unavailable: {err}"),
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_file_reads);
+criterion_main!(benches);
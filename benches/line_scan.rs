@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use regex::bytes;
+
+use rustcomb::lines::{search_each_line, search_lines};
+use rustcomb::prefilter::Prefilter;
+
+/// Roughly the size of a large log, made from the heavy test file repeated in memory.
+const BUFFER_LEN: usize = 16 * 1024 * 1024;
+
+fn buffer() -> Vec<u8> {
+    let heavy = std::fs::read(Path::new("test_files").join("heavy_file.txt"))
+        .expect("Expect to find 'test_files/heavy_file.txt'");
+
+    let mut buffer = Vec::with_capacity(BUFFER_LEN + heavy.len());
+    while buffer.len() < BUFFER_LEN {
+        buffer.extend_from_slice(&heavy);
+    }
+    buffer
+}
+
+/// The per line loop against searching the whole buffer for candidates first, for patterns
+/// with a common literal, a rare literal, several literals and no literal at all.
+fn bench_line_scan(c: &mut Criterion) {
+    let buffer = buffer();
+
+    let mut group = c.benchmark_group("line scan");
+    group.throughput(Throughput::Bytes(buffer.len() as u64));
+
+    for pattern in [
+        "cubilia",
+        "Dis dignissim pulvinar senectus",
+        "(?i)convallis|fames",
+        r"\w+ura\b",
+    ] {
+        let re = bytes::Regex::new(pattern).unwrap();
+        let prefilter = Prefilter::new(pattern);
+
+        group.bench_with_input(
+            BenchmarkId::new("each_line", pattern),
+            &buffer,
+            |b, buffer| b.iter(|| search_each_line(buffer, &re)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("prefiltered", pattern),
+            &buffer,
+            |b, buffer| b.iter(|| search_lines(buffer, &re, &prefilter)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_line_scan);
+criterion_main!(benches);
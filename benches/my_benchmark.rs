@@ -3,7 +3,7 @@ use dotenv::from_filename;
 
 mod file_generation;
 
-use file_generation::{FileType, create_files};
+use file_generation::{FileType, TreeSpec, create_files};
 use std::{collections::HashMap, path::Path};
 
 use std::sync::Arc;
@@ -11,12 +11,16 @@ use std::sync::Arc;
 use assert_fs::fixture;
 use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
 
-use rustcomb::{
-    Cli, PrintDisable, async_read_files, get_cpuworkers, rayon_read_files,
-    single_thread_read_files, thread_per_file_read_files, threadpool_read_files,
-};
+use rustcomb::{Cli, PrintDisable, get_cpuworkers};
 
+use rustcomb::cancel::CancellationToken;
+use rustcomb::colour::ColorChoice;
+use rustcomb::mmap::MmapChoice;
 use rustcomb::my_regex::SearchMode;
+use rustcomb::output::{DEFAULT_BUFFER_CAPACITY, DEFAULT_FLUSH_THRESHOLD};
+use rustcomb::sort::SortBy;
+use rustcomb::strategy::{SearchStrategy, Strategy};
+use rustcomb::tuning::DEFAULT_CHANNEL_CAPACITY;
 
 fn setup(temp_dir: &fixture::TempDir) -> Arc<Cli> {
     from_filename(Path::new("benches").join(".env")).ok();
@@ -28,13 +32,13 @@ fn setup(temp_dir: &fixture::TempDir) -> Arc<Cli> {
         .parse::<usize>()
         .unwrap();
 
-    // Not currently used
-    let num_of_directories_to_create = 0;
-    // let num_of_directories_to_create = envs
-    //     .get("NUM_OF_DIRECTORIES_TO_CREATE")
-    //     .expect("Expect to find 'NUM_OF_DIRECTORIES_TO_CREATE'")
-    //     .parse::<usize>()
-    //     .unwrap();
+    // The tree's shape is optional, by default every file is within the one directory
+    let optional = |key: &str| {
+        envs.get(key).filter(|v| !v.is_empty()).map(|v| {
+            v.parse::<u64>()
+                .unwrap_or_else(|_| panic!("Expect '{key}' to be a number"))
+        })
+    };
 
     let path_pattern_regex: SearchMode = envs
         .get("PATH_PATTERN_REGEX")
@@ -80,19 +84,24 @@ fn setup(temp_dir: &fixture::TempDir) -> Arc<Cli> {
     //     bench_print_output
     // );
 
+    let spec = TreeSpec {
+        num_of_files: num_of_files_to_create,
+        file_type: file_to_duplicate,
+        depth: optional("DIRECTORY_DEPTH").unwrap_or(0) as usize,
+        fan_out: optional("DIRECTORY_FAN_OUT").unwrap_or(0) as usize,
+        num_of_binary_files: optional("NUM_OF_BINARY_FILES").unwrap_or(0) as usize,
+        ignored_dirs: optional("IGNORED_DIRS").unwrap_or(0) != 0,
+        seed: optional("SEED").unwrap_or(0),
+    };
+
     println!("\nConfiguration:*************");
     println!(
-        "Num of files to create: {}\nFile name path regex: {:?}\nFile internal regex: {}\nFile type to duplicate: {}",
-        num_of_files_to_create, path_pattern, file_pattern, file_to_duplicate
+        "Num of files to create: {}\nFile name path regex: {:?}\nFile internal regex: {}\nFile type to duplicate: {}\nTree: {:?}",
+        num_of_files_to_create, path_pattern, file_pattern, file_to_duplicate, spec
     );
     println!("*****************************");
 
-    let p = create_files(
-        temp_dir,
-        file_to_duplicate,
-        num_of_directories_to_create,
-        num_of_files_to_create,
-    );
+    let p = create_files(temp_dir, &spec);
 
     Arc::new(Cli {
         // Initialize fields
@@ -101,6 +110,23 @@ fn setup(temp_dir: &fixture::TempDir) -> Arc<Cli> {
         file_pattern: file_pattern.to_string(),
         file_pattern_regex,
         path_pattern_regex,
+        search_archives: false,
+        files_from: None,
+        null_data: false,
+        json: false,
+        vimgrep: false,
+        emacs: false,
+        color: ColorChoice::Never,
+        colors: Vec::new(),
+        sort: SortBy::None,
+        sortr: None,
+        no_messages: false,
+        stats: false,
+        mmap: MmapChoice::Auto,
+        threads: None,
+        channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+        output_buffer: DEFAULT_BUFFER_CAPACITY,
+        flush_threshold: DEFAULT_FLUSH_THRESHOLD,
     })
 }
 
@@ -110,64 +136,22 @@ fn bench_various_reads(c: &mut Criterion) {
     let bench_print_output = PrintDisable;
 
     let mut group = c.benchmark_group("regex files search");
+    let runtime = tokio::runtime::Runtime::new().unwrap();
 
-    group.bench_with_input(
-        BenchmarkId::new(
-            format!("single_thread_read_files_PRINT_{}", bench_print_output),
-            &cli,
-        ),
-        &cli,
-        |b, s| b.iter(|| single_thread_read_files(Arc::clone(s), bench_print_output)),
-    );
-
-    group.bench_with_input(
-        BenchmarkId::new(
-            format!("thread_per_file_read_files_PRINT_{}", bench_print_output),
-            &cli,
-        ),
-        &cli,
-        |b, s| b.iter(|| thread_per_file_read_files(Arc::clone(s), bench_print_output)),
-    );
-
-    group.bench_with_input(
-        BenchmarkId::new(
-            format!("use_thread_pool_single_thread_PRINT_{}", bench_print_output),
-            &cli,
-        ),
-        &cli,
-        |b, s| b.iter(|| threadpool_read_files(Arc::clone(s), bench_print_output, 1)),
-    );
-
-    let num_of_workers = get_cpuworkers();
-    group.bench_with_input(
-        BenchmarkId::new(
-            format!(
-                "use_thread_pool_multiple_{}_PRINT_{}",
-                num_of_workers, bench_print_output
+    for strategy in Strategy::registered(get_cpuworkers()) {
+        group.bench_with_input(
+            BenchmarkId::new(
+                format!("{}_PRINT_{}", strategy.name(), bench_print_output),
+                &cli,
             ),
             &cli,
-        ),
-        &cli,
-        |b, s| b.iter(|| threadpool_read_files(Arc::clone(s), bench_print_output, num_of_workers)),
-    );
-
-    group.bench_with_input(
-        BenchmarkId::new(
-            format!("rayon_read_files_PRINT_{}", bench_print_output),
-            &cli,
-        ),
-        &cli,
-        |b, s| b.iter(|| rayon_read_files(Arc::clone(s), bench_print_output)),
-    );
-
-    group.bench_with_input(
-        BenchmarkId::new(
-            format!("async_read_files_PRINT_{}", bench_print_output),
-            &cli,
-        ),
-        &cli,
-        |b, s| b.iter(|| async { async_read_files(Arc::clone(s), bench_print_output).await }),
-    );
+            |b, s| {
+                b.iter(|| {
+                    runtime.block_on(strategy.run(s, bench_print_output, CancellationToken::new()))
+                })
+            },
+        );
+    }
 
     group.finish();
 
@@ -0,0 +1,34 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustcomb::lines::{count_lines, search_each_line};
+use rustcomb::my_regex::Matcher;
+
+// Any pattern over any bytes, the whole buffer search has to agree with searching line by line
+fuzz_target!(|input: (String, Vec<u8>)| {
+    let (pattern, buffer) = input;
+    if pattern.len() > 64 {
+        return;
+    }
+    let Ok(matcher) = Matcher::new(&pattern) else {
+        return;
+    };
+
+    let found = matcher.find_lines(&buffer);
+    assert_eq!(found, search_each_line(&buffer, matcher.bytes()));
+
+    for line in &found {
+        assert_eq!(
+            &buffer[line.byte_offset..line.byte_offset + line.line.len()],
+            &line.line[..]
+        );
+        assert_eq!(
+            line.line_number,
+            count_lines(&buffer[..line.byte_offset]) + 1
+        );
+        assert!(!line.submatches.is_empty());
+        for range in &line.submatches {
+            assert!(range.start < range.end && range.end <= line.line.len());
+        }
+    }
+});
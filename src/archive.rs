@@ -0,0 +1,446 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use flate2::read::GzDecoder;
+
+use crate::{MyErrors, Operation};
+
+/// Archive formats that can be searched as if they were directories.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Identifies an archive purely on its extension (case-insensitive).
+    pub fn from_path(path: &Path) -> Option<ArchiveKind> {
+        let filename = path.file_name()?.to_str()?.to_lowercase();
+        if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if filename.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if filename.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// A single file held within an archive, with its contents already decompressed.
+pub struct ArchiveMember {
+    /// Path of the member relative to the root of the archive, e.g. `docs/inside.txt`
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+impl ArchiveMember {
+    /// The final component of the member path, which is what path patterns are applied to.
+    pub fn filename(&self) -> &str {
+        member_filename(&self.name)
+    }
+}
+
+fn member_filename(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// How a member is reported, e.g. `release.tar.gz!/docs/inside.txt`.
+pub fn member_path(archive: &Path, name: &str) -> PathBuf {
+    PathBuf::from(format!("{}!/{}", archive.display(), name))
+}
+
+/// A member decompressing to more than this is reported rather than read, so a zip bomb can't
+/// exhaust memory.
+pub const MAX_MEMBER_LEN: u64 = 1024 * 1024 * 1024;
+/// The most preallocated for a member from the size its header claims, which may be forged.
+const MAX_PREALLOC: u64 = 16 * 1024 * 1024;
+
+/// The members of an archive, each decompressed as the iterator reaches it.
+pub type Members = Box<dyn Iterator<Item = Result<ArchiveMember, MyErrors>> + Send>;
+
+/**
+ * Reads every regular file within the archive, one member at a time.
+ *
+ * `keep` is called with the filename of each member before it is decompressed, so members
+ * rejected by the path pattern are skipped without being read into memory. Only the member being
+ * searched (and the next one) are held, so an archive never has to fit in memory.
+ *
+ * A member which fails to read is an error against `archive!/member`, the members before it are
+ * still yielded. A zip's later members are read too, but a tar can't be read past a broken entry.
+ */
+pub fn read_members<F>(path: &Path, kind: ArchiveKind, keep: F) -> Members
+where
+    F: Fn(&str) -> bool + Send + 'static,
+{
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Box::new(std::iter::once(Err(MyErrors::io(path, Operation::Open, e)))),
+    };
+    let reader = BufReader::new(file);
+
+    match kind {
+        ArchiveKind::Tar => read_tar_members(path, reader, keep),
+        ArchiveKind::TarGz => read_tar_members(path, GzDecoder::new(reader), keep),
+        ArchiveKind::Zip => read_zip_members(path, reader, keep),
+    }
+}
+
+/// `tar::Entries` borrows its archive, so members are read on a thread of their own and handed
+/// over one at a time. The thread stops once the iterator is dropped.
+fn read_tar_members<R, F>(path: &Path, reader: R, keep: F) -> Members
+where
+    R: Read + Send + 'static,
+    F: Fn(&str) -> bool + Send + 'static,
+{
+    let (tx, rx) = crossbeam_channel::bounded(0);
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        let read_err = |e| MyErrors::io(&path, Operation::Read, e);
+        let mut archive = tar::Archive::new(reader);
+        let entries = match archive.entries() {
+            Ok(entries) => entries,
+            Err(e) => {
+                let _ = tx.send(Err(read_err(e)));
+                return;
+            }
+        };
+
+        for entry in entries {
+            let member = match entry {
+                Ok(entry) => read_tar_member(&path, entry, &keep),
+                Err(e) => Some(Err(read_err(e))),
+            };
+            let Some(member) = member else {
+                continue;
+            };
+            let failed = member.is_err();
+            if tx.send(member).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    Box::new(rx.into_iter())
+}
+
+/// `None` for entries which aren't searched: directories, links and those `keep` rejects.
+fn read_tar_member<R: Read>(
+    path: &Path,
+    mut entry: tar::Entry<R>,
+    keep: &impl Fn(&str) -> bool,
+) -> Option<Result<ArchiveMember, MyErrors>> {
+    if !entry.header().entry_type().is_file() {
+        return None;
+    }
+
+    let name = match entry.path() {
+        Ok(name) => name.to_string_lossy().into_owned(),
+        Err(e) => return Some(Err(MyErrors::io(path, Operation::Read, e))),
+    };
+    if !keep(member_filename(&name)) {
+        return None;
+    }
+
+    let size = entry.size();
+    let read = read_member(&mut entry, size, MAX_MEMBER_LEN).and_then(|contents| {
+        // A truncated tar ends the entry early rather than failing the read
+        if contents.len() as u64 == size {
+            Ok(contents)
+        } else {
+            Err(io::ErrorKind::UnexpectedEof.into())
+        }
+    });
+    Some(match read {
+        Ok(contents) => Ok(ArchiveMember { name, contents }),
+        Err(e) => Err(MyErrors::io(member_path(path, &name), Operation::Read, e)),
+    })
+}
+
+fn read_zip_members<R, F>(path: &Path, reader: R, keep: F) -> Members
+where
+    R: Read + Seek + Send + 'static,
+    F: Fn(&str) -> bool + Send + 'static,
+{
+    let path = path.to_path_buf();
+    let mut archive = match zip::ZipArchive::new(reader) {
+        Ok(archive) => archive,
+        Err(source) => return Box::new(std::iter::once(Err(MyErrors::Zip { path, source }))),
+    };
+
+    Box::new(
+        (0..archive.len()).filter_map(move |idx| read_zip_member(&path, &mut archive, idx, &keep)),
+    )
+}
+
+/// `None` for entries which aren't searched, as `read_tar_member`.
+fn read_zip_member<R: Read + Seek>(
+    path: &Path,
+    archive: &mut zip::ZipArchive<R>,
+    idx: usize,
+    keep: &impl Fn(&str) -> bool,
+) -> Option<Result<ArchiveMember, MyErrors>> {
+    let mut entry = match archive.by_index(idx) {
+        Ok(entry) => entry,
+        Err(source) => {
+            return Some(Err(MyErrors::Zip {
+                path: path.to_path_buf(),
+                source,
+            }));
+        }
+    };
+    if !entry.is_file() {
+        return None;
+    }
+
+    let name = entry.name().to_string();
+    if !keep(member_filename(&name)) {
+        return None;
+    }
+
+    let size = entry.size();
+    Some(match read_member(&mut entry, size, MAX_MEMBER_LEN) {
+        Ok(contents) => Ok(ArchiveMember { name, contents }),
+        Err(e) => Err(MyErrors::io(member_path(path, &name), Operation::Read, e)),
+    })
+}
+
+/// Reads a member whose header claims `size` bytes, failing rather than reading past `limit`.
+fn read_member(reader: impl Read, size: u64, limit: u64) -> io::Result<Vec<u8>> {
+    let too_large = || {
+        io::Error::new(
+            io::ErrorKind::FileTooLarge,
+            format!("member is larger than the {limit} byte limit"),
+        )
+    };
+    if size > limit {
+        return Err(too_large());
+    }
+
+    // The size may be wrong, so only the reader's own end is trusted
+    let mut contents = Vec::with_capacity(size.min(MAX_PREALLOC) as usize);
+    reader.take(limit + 1).read_to_end(&mut contents)?;
+    if contents.len() as u64 > limit {
+        return Err(too_large());
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+
+    use assert_fs::TempDir;
+    use flate2::{Compression, write::GzEncoder};
+
+    use super::{ArchiveKind, ArchiveMember, read_member, read_members};
+    use crate::MyErrors;
+
+    #[test]
+    fn test_archive_kind_from_path() {
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("release.TAR.GZ")),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("release.tgz")),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("release.tar")),
+            Some(ArchiveKind::Tar)
+        );
+        assert_eq!(
+            ArchiveKind::from_path(Path::new("bundle.zip")),
+            Some(ArchiveKind::Zip)
+        );
+        assert_eq!(ArchiveKind::from_path(Path::new("notes.txt")), None);
+    }
+
+    #[test]
+    fn test_read_zip_members() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("bundle.zip");
+
+        let mut writer = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("docs/inside.txt", options).unwrap();
+        writer.write_all(b"hello from zip").unwrap();
+        writer.start_file("docs/skipped.md", options).unwrap();
+        writer.write_all(b"not wanted").unwrap();
+        writer.finish().unwrap();
+
+        let members: Vec<ArchiveMember> = read_members(&archive_path, ArchiveKind::Zip, |name| {
+            name.ends_with(".txt")
+        })
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].name, "docs/inside.txt");
+        assert_eq!(members[0].filename(), "inside.txt");
+        assert_eq!(members[0].contents, b"hello from zip");
+
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_read_tar_gz_members() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("release.tar.gz");
+
+        let encoder = GzEncoder::new(File::create(&archive_path).unwrap(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let data = b"hello from tar";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "nested/inside.txt", &data[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let members: Vec<ArchiveMember> = read_members(&archive_path, ArchiveKind::TarGz, |_| true)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].name, "nested/inside.txt");
+        assert_eq!(members[0].contents, b"hello from tar");
+
+        temp_dir.close().unwrap();
+    }
+
+    /// Members are written uncompressed, so they can be truncated or corrupted in place.
+    fn tar_with_members(path: &Path, members: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for (name, data) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap();
+    }
+
+    #[test]
+    fn test_truncated_tar_keeps_earlier_members() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("release.tar");
+        let second = vec![b'x'; 4096];
+        tar_with_members(
+            &archive_path,
+            &[("first.txt", b"needle"), ("second.txt", &second)],
+        );
+        // Cut off part way through the second member's contents
+        let contents = std::fs::read(&archive_path).unwrap();
+        std::fs::write(&archive_path, &contents[..512 * 4 + 100]).unwrap();
+
+        let members: Vec<_> = read_members(&archive_path, ArchiveKind::Tar, |_| true).collect();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].as_ref().unwrap().contents, b"needle");
+        let err = members[1].as_ref().err().unwrap();
+        assert_eq!(
+            err.path(),
+            Some(temp_dir.path().join("release.tar!/second.txt").as_path())
+        );
+
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_forged_member_size_is_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("release.tar");
+        tar_with_members(&archive_path, &[("first.txt", b"needle")]);
+
+        // A header claiming far more than the archive holds, or memory could ever allocate
+        let mut header = tar::Header::new_gnu();
+        header.set_path("forged.txt").unwrap();
+        header.set_size(u64::MAX / 2);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut contents = std::fs::read(&archive_path).unwrap();
+        // Replaces the end of archive marker
+        contents.truncate(512 * 2);
+        contents.extend_from_slice(header.as_bytes());
+        contents.extend_from_slice(&[b'x'; 512]);
+        std::fs::write(&archive_path, contents).unwrap();
+
+        let members: Vec<_> = read_members(&archive_path, ArchiveKind::Tar, |_| true).collect();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].as_ref().unwrap().contents, b"needle");
+        let err = members[1].as_ref().err().unwrap();
+        assert_eq!(
+            err.path(),
+            Some(temp_dir.path().join("release.tar!/forged.txt").as_path())
+        );
+        assert_eq!(err.code(), "io_read");
+
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_read_member_is_limited() {
+        let data = [b'x'; 100];
+        assert_eq!(read_member(&data[..], 100, 100).unwrap().len(), 100);
+        // A size which understates the contents, as a zip bomb's might
+        assert!(read_member(&data[..], 10, 99).is_err());
+        assert!(read_member(&data[..], u64::MAX, 100).is_err());
+        // Nothing is preallocated past what's actually there
+        assert_eq!(
+            read_member(&data[..10], 1 << 40, u64::MAX - 1)
+                .unwrap()
+                .len(),
+            10
+        );
+    }
+
+    #[test]
+    fn test_corrupt_zip_member_is_reported_against_it() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("bundle.zip");
+
+        let mut writer = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for (name, data) in [
+            ("a.txt", "first"),
+            ("b.txt", "corrupted"),
+            ("c.txt", "last"),
+        ] {
+            writer.start_file(name, options).unwrap();
+            writer.write_all(data.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        // Flipping a byte of the stored contents fails its checksum once read
+        let mut contents = std::fs::read(&archive_path).unwrap();
+        let at = contents
+            .windows(9)
+            .position(|window| window == b"corrupted")
+            .unwrap();
+        contents[at] = b'C';
+        std::fs::write(&archive_path, contents).unwrap();
+
+        let members: Vec<Result<ArchiveMember, MyErrors>> =
+            read_members(&archive_path, ArchiveKind::Zip, |_| true).collect();
+        assert_eq!(members.len(), 3);
+        assert_eq!(members[0].as_ref().unwrap().name, "a.txt");
+        assert_eq!(
+            members[1].as_ref().err().unwrap().path(),
+            Some(temp_dir.path().join("bundle.zip!/b.txt").as_path())
+        );
+        assert_eq!(members[2].as_ref().unwrap().contents, b"last");
+
+        temp_dir.close().unwrap();
+    }
+}
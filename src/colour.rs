@@ -0,0 +1,244 @@
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+use ansi_term::{Colour, Style};
+
+/// Environment variable which disables colour when set to anything, see https://no-color.org
+pub const NO_COLOR: &str = "NO_COLOR";
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum ColorChoice {
+    #[clap(
+        name = "auto",
+        help = "Colour when stdout is a terminal and NO_COLOR isn't set"
+    )]
+    Auto,
+    #[clap(name = "always", help = "Always colour, even when piped")]
+    Always,
+    #[clap(name = "never", help = "Never colour")]
+    Never,
+}
+
+impl ColorChoice {
+    pub fn use_colour(&self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os(NO_COLOR).is_some_and(|v| !v.is_empty());
+                !no_color && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+/// The styles applied to each part of the standard output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColourSpecs {
+    pub path: Style,
+    pub line: Style,
+    pub matched: Style,
+}
+
+impl Default for ColourSpecs {
+    fn default() -> Self {
+        ColourSpecs {
+            path: Colour::Green.normal(),
+            line: Colour::Green.normal(),
+            matched: Colour::Red.normal(),
+        }
+    }
+}
+
+impl ColourSpecs {
+    /// No escape codes are written at all.
+    pub fn plain() -> Self {
+        ColourSpecs {
+            path: Style::new(),
+            line: Style::new(),
+            matched: Style::new(),
+        }
+    }
+
+    pub fn new(choice: ColorChoice, specs: &[ColourSpec]) -> Self {
+        if !choice.use_colour() {
+            return ColourSpecs::plain();
+        }
+
+        let mut colours = ColourSpecs::default();
+        for spec in specs {
+            colours.apply(spec);
+        }
+        colours
+    }
+
+    pub fn apply(&mut self, spec: &ColourSpec) {
+        let style = match spec.target {
+            Target::Path => &mut self.path,
+            Target::Line => &mut self.line,
+            Target::Match => &mut self.matched,
+        };
+
+        *style = match spec.change {
+            Change::None => Style::new(),
+            Change::Foreground(colour) => Style {
+                foreground: Some(colour),
+                ..*style
+            },
+            Change::Background(colour) => Style {
+                background: Some(colour),
+                ..*style
+            },
+            Change::Bold => style.bold(),
+            Change::Italic => style.italic(),
+            Change::Underline => style.underline(),
+            Change::Dimmed => style.dimmed(),
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    Path,
+    Line,
+    Match,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    None,
+    Foreground(Colour),
+    Background(Colour),
+    Bold,
+    Italic,
+    Underline,
+    Dimmed,
+}
+
+/**
+ * A single `--colors` customisation, following ripgrep's `{type}:{attribute}:{value}` format.
+ *
+ * e.g. `match:fg:yellow`, `line:bg:0,0,255`, `path:style:bold` or `match:none` to remove the styling.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColourSpec {
+    target: Target,
+    change: Change,
+}
+
+impl FromStr for ColourSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+
+        let target = match parts[0].to_lowercase().as_str() {
+            "path" => Target::Path,
+            "line" => Target::Line,
+            "match" => Target::Match,
+            other => {
+                return Err(format!(
+                    "Unknown colour type '{}'. Required to be either [path, line or match]",
+                    other
+                ));
+            }
+        };
+
+        let change = match parts[1..] {
+            ["none"] => Change::None,
+            ["fg", value] => Change::Foreground(parse_colour(value)?),
+            ["bg", value] => Change::Background(parse_colour(value)?),
+            ["style", value] => match value.to_lowercase().as_str() {
+                "bold" => Change::Bold,
+                "italic" => Change::Italic,
+                "underline" => Change::Underline,
+                "dimmed" => Change::Dimmed,
+                _ => {
+                    return Err(format!(
+                        "Unknown style '{}'. Required to be either [bold, italic, underline or dimmed]",
+                        value
+                    ));
+                }
+            },
+            _ => {
+                return Err(format!(
+                    "'{}' is expected to be either {{type}}:{{fg|bg|style}}:{{value}} or {{type}}:none",
+                    s
+                ));
+            }
+        };
+
+        Ok(ColourSpec { target, change })
+    }
+}
+
+/// Named colours, a 256 colour palette number or `r,g,b`.
+fn parse_colour(value: &str) -> Result<Colour, String> {
+    let colour = match value.to_lowercase().as_str() {
+        "black" => Colour::Black,
+        "red" => Colour::Red,
+        "green" => Colour::Green,
+        "yellow" => Colour::Yellow,
+        "blue" => Colour::Blue,
+        "magenta" | "purple" => Colour::Purple,
+        "cyan" => Colour::Cyan,
+        "white" => Colour::White,
+        other => {
+            let numbers = other
+                .split(',')
+                .map(|n| n.trim().parse::<u8>())
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| format!("Unknown colour '{}'", value))?;
+
+            match numbers[..] {
+                [n] => Colour::Fixed(n),
+                [r, g, b] => Colour::RGB(r, g, b),
+                _ => return Err(format!("Unknown colour '{}'", value)),
+            }
+        }
+    };
+
+    Ok(colour)
+}
+
+#[cfg(test)]
+mod tests {
+    use ansi_term::{Colour, Style};
+
+    use super::{ColorChoice, ColourSpec, ColourSpecs};
+
+    #[test]
+    fn test_never_is_plain() {
+        let specs = ["match:fg:yellow".parse::<ColourSpec>().unwrap()];
+        assert_eq!(
+            ColourSpecs::new(ColorChoice::Never, &specs),
+            ColourSpecs::plain()
+        );
+    }
+
+    #[test]
+    fn test_apply_specs() {
+        let specs: Vec<ColourSpec> = [
+            "match:fg:yellow",
+            "match:style:bold",
+            "line:none",
+            "path:bg:1,2,3",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+
+        let colours = ColourSpecs::new(ColorChoice::Always, &specs);
+        assert_eq!(colours.matched, Colour::Yellow.bold());
+        assert_eq!(colours.line, Style::new());
+        assert_eq!(colours.path, Colour::Green.on(Colour::RGB(1, 2, 3)));
+    }
+
+    #[test]
+    fn test_invalid_specs() {
+        assert!("title:fg:red".parse::<ColourSpec>().is_err());
+        assert!("match:fg:orange".parse::<ColourSpec>().is_err());
+        assert!("match:style:blink".parse::<ColourSpec>().is_err());
+        assert!("match:fg".parse::<ColourSpec>().is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::{Cli, MyErrors, Operation};
+
+/// Passing this as a path reads from standard input instead.
+pub const STDIN_ARG: &str = "-";
+
+/// Name reported for matches found within standard input.
+pub const STDIN_NAME: &str = "<stdin>";
+
+/// Where the files to be searched come from.
+pub enum Input {
+    /// A file or directory on disk, directories are walked recursively.
+    Path(PathBuf),
+    /// Data piped in through standard input, searched as a single file.
+    Stdin(Vec<u8>),
+}
+
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN_ARG
+}
+
+/**
+ * Standard input can only be consumed once per process, however each strategy resolves its own
+ * inputs. So the first read is kept and handed out to every later caller.
+ */
+fn read_stdin() -> Result<&'static [u8], MyErrors> {
+    static STDIN: OnceLock<Result<Vec<u8>, (io::ErrorKind, String)>> = OnceLock::new();
+
+    STDIN
+        .get_or_init(|| {
+            let mut buffer = Vec::new();
+            io::stdin()
+                .lock()
+                .read_to_end(&mut buffer)
+                .map(|_| buffer)
+                .map_err(|err| (err.kind(), err.to_string()))
+        })
+        .as_deref()
+        .map_err(|(kind, msg)| {
+            MyErrors::io(
+                STDIN_NAME,
+                Operation::Read,
+                io::Error::new(*kind, msg.clone()),
+            )
+        })
+}
+
+/// Works out the inputs requested by the command line: a walked path, standard input or a list of files.
+pub fn resolve_inputs(args: &Cli) -> Result<Vec<Input>, MyErrors> {
+    match &args.files_from {
+        Some(list) => {
+            if is_stdin(&args.path) {
+                return Err(MyErrors::InvalidArgs(
+                    "'-' can't be used as the path together with '--files-from'".to_string(),
+                ));
+            }
+
+            let contents = if is_stdin(list) {
+                read_stdin()?.to_vec()
+            } else {
+                std::fs::read(list).map_err(|e| MyErrors::io(list, Operation::Read, e))?
+            };
+
+            Ok(parse_file_list(&contents, &args.path, args.null_data)
+                .into_iter()
+                .map(Input::Path)
+                .collect())
+        }
+        None if is_stdin(&args.path) => Ok(vec![Input::Stdin(read_stdin()?.to_vec())]),
+        None => Ok(vec![Input::Path(args.path.clone())]),
+    }
+}
+
+/**
+ * Splits a file list (as produced by `find`, `git ls-files`, ...) into paths.
+ *
+ * Entries are separated by newlines, or NUL bytes when `null_data` is set, and relative entries are
+ * resolved against `base`. Blank entries are skipped.
+ */
+pub fn parse_file_list(contents: &[u8], base: &Path, null_data: bool) -> Vec<PathBuf> {
+    let separator = if null_data { b'\0' } else { b'\n' };
+
+    contents
+        .split(|&b| b == separator)
+        .map(|entry| {
+            if null_data {
+                entry
+            } else {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| base.join(path_from_bytes(entry)))
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::parse_file_list;
+
+    #[test]
+    fn test_parse_file_list_newlines() {
+        let paths = parse_file_list(
+            b"a.txt\r\nsub/b.txt\n\n/abs/c.txt\n",
+            Path::new("base"),
+            false,
+        );
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("base/a.txt"),
+                PathBuf::from("base/sub/b.txt"),
+                PathBuf::from("/abs/c.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_file_list_null_data() {
+        let paths = parse_file_list(b"with\nnewline.txt\0b.txt\0", Path::new("base"), true);
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("base/with\nnewline.txt"),
+                PathBuf::from("base/b.txt"),
+            ]
+        );
+    }
+}
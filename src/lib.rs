@@ -1,14 +1,22 @@
-use ansi_term::Colour;
+use archive::ArchiveKind;
+use cancel::CancellationToken;
 use clap::Parser;
+use colour::{ColorChoice, ColourSpec};
 use core::fmt;
-use futures::TryStreamExt;
-use futures::stream::{self, StreamExt};
-use memmap2::MmapOptions;
-use my_regex::SearchMode;
+use futures::future::{self, Either};
+use futures::stream::{self, Stream, StreamExt};
+use input::Input;
+use lines::LineTerminator;
+use mmap::MmapChoice;
+use my_regex::{Matcher, SearchMode};
+use output::{Output, Summary};
 use rayon::prelude::*;
 use regex::Regex;
-use regex::bytes;
-use std::borrow::Cow;
+use serde::Serialize;
+use sort::{ReorderBuffer, Sort, SortBy};
+use stats::{Outcome, Stage, Stats, TimedWalk};
+use std::any::Any;
+use std::collections::VecDeque;
 use std::error;
 use std::error::Error;
 use std::fmt::Display;
@@ -16,27 +24,39 @@ use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::BufWriter;
-use std::io::StdoutLock;
 use std::io::{self, Write};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::OnceLock;
 use std::sync::PoisonError;
-use std::sync::atomic::AtomicUsize;
-use std::sync::atomic::Ordering;
 use std::thread;
+use std::time::{Duration, Instant};
 use threadpool::ThreadPool;
 use tokio::io::AsyncReadExt;
-use walkdir::WalkDir;
-
+use tuning::Tuning;
+
+pub mod archive;
+pub mod cancel;
+pub mod colour;
+pub mod input;
+pub mod lines;
+pub mod mmap;
 pub mod my_regex;
+pub mod output;
+pub mod prefilter;
+pub mod sort;
+pub mod stats;
+pub mod strategy;
+pub mod tuning;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+pub mod uring;
 /// This trait (and its implementation) are more to experiment with this behaviour rather than
 /// an required bit of functionality.
 /// However it should result "logic" shifting from runtime to compile-time so should be beneficial too.
-pub trait Printable: Send + 'static + Copy + Clone {
-    fn writeln<F>(&self, data: Vec<(String, Vec<String>)>, func: F) -> Result<(), MyErrors>
-    where
-        F: FnOnce(Vec<(String, Vec<String>)>) -> Result<(), MyErrors>;
+pub trait Printable: Send + 'static + Clone {
     fn writeln_w_handler<T, F>(&self, handler: &mut BufWriter<T>, func: F) -> Result<(), MyErrors>
     where
         T: std::io::Write,
@@ -44,12 +64,12 @@ pub trait Printable: Send + 'static + Copy + Clone {
     fn information_out<T, F>(
         &self,
         handler: &mut BufWriter<T>,
-        data: (String, Vec<String>),
+        data: FileMatches,
         func: F,
     ) -> Result<(), MyErrors>
     where
         T: std::io::Write,
-        F: FnOnce(&mut BufWriter<T>, (String, Vec<String>)) -> Result<(), MyErrors>;
+        F: FnOnce(&mut BufWriter<T>, FileMatches) -> Result<(), MyErrors>;
 }
 
 #[derive(Clone, Copy)]
@@ -70,13 +90,6 @@ impl fmt::Display for PrintDisable {
 }
 
 impl Printable for PrintEnabled {
-    fn writeln<F>(&self, data: Vec<(String, Vec<String>)>, func: F) -> Result<(), MyErrors>
-    where
-        F: FnOnce(Vec<(String, Vec<String>)>) -> Result<(), MyErrors>,
-    {
-        func(data)
-    }
-
     fn writeln_w_handler<T, F>(
         &self,
         handler: &mut BufWriter<T>,
@@ -92,25 +105,18 @@ impl Printable for PrintEnabled {
     fn information_out<T, F>(
         &self,
         handler: &mut BufWriter<T>,
-        data: (String, Vec<String>),
+        data: FileMatches,
         func: F,
     ) -> Result<(), MyErrors>
     where
         T: std::io::Write,
-        F: FnOnce(&mut BufWriter<T>, (String, Vec<String>)) -> Result<(), MyErrors>,
+        F: FnOnce(&mut BufWriter<T>, FileMatches) -> Result<(), MyErrors>,
     {
         func(handler, data)
     }
 }
 
 impl Printable for PrintDisable {
-    fn writeln<F>(&self, _: Vec<(String, Vec<String>)>, _: F) -> Result<(), MyErrors>
-    where
-        F: FnOnce(Vec<(String, Vec<String>)>) -> Result<(), MyErrors>,
-    {
-        Ok(())
-    }
-
     fn writeln_w_handler<T, F>(
         &self,
         _: &mut BufWriter<T>,
@@ -126,40 +132,170 @@ impl Printable for PrintDisable {
     fn information_out<T, F>(
         &self,
         _: &mut BufWriter<T>,
-        _: (String, Vec<String>),
+        _: FileMatches,
         _: F,
     ) -> Result<(), MyErrors>
     where
         T: std::io::Write,
-        F: FnOnce(&mut BufWriter<T>, (String, Vec<String>)) -> Result<(), MyErrors>,
+        F: FnOnce(&mut BufWriter<T>, FileMatches) -> Result<(), MyErrors>,
     {
         Ok(())
     }
 }
 
+/// What was being attempted when an IO error occurred.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Open,
+    Mmap,
+    Read,
+    Walk,
+    Write,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Operation::Open => write!(f, "open"),
+            Operation::Mmap => write!(f, "mmap"),
+            Operation::Read => write!(f, "read"),
+            Operation::Walk => write!(f, "walk"),
+            Operation::Write => write!(f, "write"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum MyErrors {
+    /// The file or path pattern isn't a valid regex
     Regex(regex::Error),
-    WalkDir(walkdir::Error),
-    FileIO(io::Error),
-    Utf8Error(std::string::FromUtf8Error),
+    /// No file pattern was given to search for
+    MissingPattern,
+    WalkDir {
+        path: PathBuf,
+        source: walkdir::Error,
+    },
+    FileIO {
+        path: PathBuf,
+        operation: Operation,
+        source: io::Error,
+    },
+    /// Every strategy searches bytes, only the unused `find_entry_with_file_async` decodes files
+    /// as `str` and can hit this
+    Utf8Error {
+        path: PathBuf,
+        source: std::string::FromUtf8Error,
+    },
+    Zip {
+        path: PathBuf,
+        source: zip::result::ZipError,
+    },
+    /// Writing results (rather than reading files) failed, e.g. a closed pipe
+    Output(io::Error),
     LockError(String),
     ThreadPanic(String),
-    SomeError(String),
     TokioError(tokio::task::JoinError),
+    InvalidArgs(String),
 }
 
 impl fmt::Display for MyErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
             MyErrors::Regex(ref e) => write!(f, "regex error: ({})", e),
-            MyErrors::WalkDir(ref e) => write!(f, "WalkDir error: ({})", e),
-            MyErrors::FileIO(ref e) => write!(f, "File IO error: ({})", e),
+            MyErrors::MissingPattern => write!(f, "a file pattern is required"),
+            MyErrors::WalkDir {
+                ref path,
+                ref source,
+            } => {
+                write!(f, "WalkDir error for {}: ({})", path.display(), source)
+            }
+            MyErrors::FileIO {
+                ref path,
+                operation,
+                ref source,
+            } => write!(
+                f,
+                "File IO error, failed to {} {}: ({})",
+                operation,
+                path.display(),
+                source
+            ),
+            MyErrors::Utf8Error {
+                ref path,
+                ref source,
+            } => {
+                write!(f, "UTF8 error in {}: ({})", path.display(), source)
+            }
+            MyErrors::Zip {
+                ref path,
+                ref source,
+            } => {
+                write!(f, "Zip archive error in {}: ({})", path.display(), source)
+            }
+            MyErrors::Output(ref e) => write!(f, "failed to write output: ({})", e),
             MyErrors::LockError(ref e) => write!(f, "Lock error ({})", e),
             MyErrors::ThreadPanic(ref e) => write!(f, "thread error ({})", e),
-            MyErrors::SomeError(ref e) => write!(f, "value expected to be not None ({})", e),
-            MyErrors::Utf8Error(ref e) => write!(f, "UTF8 error ({})", e),
             MyErrors::TokioError(ref e) => write!(f, "TokioError error ({})", e),
+            MyErrors::InvalidArgs(ref e) => write!(f, "invalid arguments ({})", e),
+        }
+    }
+}
+
+impl MyErrors {
+    pub fn io(path: impl Into<PathBuf>, operation: Operation, source: io::Error) -> Self {
+        MyErrors::FileIO {
+            path: path.into(),
+            operation,
+            source,
+        }
+    }
+
+    /**
+     * A stable identifier for scripts and the JSON output, unlike the messages these won't change.
+     *
+     * IO errors are split by the operation which failed, e.g. `io_open` or `io_mmap`.
+     */
+    pub fn code(&self) -> &'static str {
+        match *self {
+            MyErrors::Regex(_) => "invalid_pattern",
+            MyErrors::MissingPattern => "missing_pattern",
+            MyErrors::WalkDir { .. } => "walk",
+            MyErrors::FileIO { operation, .. } => match operation {
+                Operation::Open => "io_open",
+                Operation::Mmap => "io_mmap",
+                Operation::Read => "io_read",
+                Operation::Walk => "io_walk",
+                Operation::Write => "io_write",
+            },
+            MyErrors::Utf8Error { .. } => "invalid_utf8",
+            MyErrors::Zip { .. } => "invalid_archive",
+            MyErrors::Output(_) => "output",
+            MyErrors::LockError(_) => "lock_poisoned",
+            MyErrors::ThreadPanic(_) => "thread_panic",
+            MyErrors::TokioError(_) => "task_failed",
+            MyErrors::InvalidArgs(_) => "invalid_args",
+        }
+    }
+
+    /// The file (or directory) the error occurred for, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match *self {
+            MyErrors::WalkDir { ref path, .. }
+            | MyErrors::FileIO { ref path, .. }
+            | MyErrors::Utf8Error { ref path, .. }
+            | MyErrors::Zip { ref path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    pub fn operation(&self) -> Option<Operation> {
+        match *self {
+            MyErrors::WalkDir { .. } => Some(Operation::Walk),
+            MyErrors::FileIO { operation, .. } => Some(operation),
+            MyErrors::Utf8Error { .. } | MyErrors::Zip { .. } => Some(Operation::Read),
+            MyErrors::Output(_) => Some(Operation::Write),
+            _ => None,
         }
     }
 }
@@ -168,13 +304,16 @@ impl error::Error for MyErrors {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
             MyErrors::Regex(ref e) => Some(e),
-            MyErrors::WalkDir(ref e) => Some(e),
-            MyErrors::FileIO(ref e) => Some(e),
+            MyErrors::MissingPattern => None,
+            MyErrors::WalkDir { ref source, .. } => Some(source),
+            MyErrors::FileIO { ref source, .. } => Some(source),
+            MyErrors::Utf8Error { ref source, .. } => Some(source),
+            MyErrors::Zip { ref source, .. } => Some(source),
+            MyErrors::Output(ref e) => Some(e),
             MyErrors::LockError(_) => None,
             MyErrors::ThreadPanic(_) => None,
-            MyErrors::SomeError(_) => None,
-            MyErrors::Utf8Error(ref e) => Some(e),
             MyErrors::TokioError(ref e) => Some(e),
+            MyErrors::InvalidArgs(_) => None,
         }
     }
 }
@@ -192,7 +331,7 @@ where
 #[clap(name = "Rustcomb")]
 // TODO: Add short flag support
 pub struct Cli {
-    /// The directory to search within
+    /// The directory to search within, or `-` to search standard input
     pub path: std::path::PathBuf,
 
     /// Pattern matching mode for within the file
@@ -208,6 +347,76 @@ pub struct Cli {
 
     /// The file name pattern to look for
     pub path_pattern: Option<String>,
+
+    /// Search inside `.tar`, `.tar.gz` and `.zip` archives, treating each as a directory
+    #[clap(short = 'z', long)]
+    pub search_archives: bool,
+
+    /// Search the files listed in FILE (`-` for standard input) instead of walking the path.
+    /// Relative entries are resolved against the path
+    #[clap(long, value_name = "FILE")]
+    pub files_from: Option<PathBuf>,
+
+    /// Entries within `--files-from` are separated by NUL bytes rather than newlines
+    #[clap(long, requires = "files_from")]
+    pub null_data: bool,
+
+    /// Print results as JSON Lines, one object per begin/match/end/summary event (as ripgrep's `--json`)
+    #[clap(long, conflicts_with_all = ["vimgrep", "emacs"])]
+    pub json: bool,
+
+    /// Print every match as `path:line:column:text`, for vim's quickfix list
+    #[clap(long, conflicts_with = "emacs")]
+    pub vimgrep: bool,
+
+    /// Print every match as `path:line:column: text`, for Emacs' `compilation-mode`
+    #[clap(long)]
+    pub emacs: bool,
+
+    /// When to use colours
+    #[clap(long, default_value = "auto", value_name = "WHEN", value_parser = clap::builder::EnumValueParser::<ColorChoice>::new())]
+    pub color: ColorChoice,
+
+    /// Customise colours as `{type}:{attribute}:{value}`, e.g. `match:fg:yellow` or `path:style:bold`.
+    /// Types are path, line and match, attributes are fg, bg and style. `{type}:none` clears a type
+    #[clap(long, value_name = "SPEC")]
+    pub colors: Vec<ColourSpec>,
+
+    /// Print results in ascending order. Parallel strategies reorder their results as they arrive
+    #[clap(long, default_value = "none", value_name = "SORTBY", value_parser = clap::builder::EnumValueParser::<SortBy>::new())]
+    pub sort: SortBy,
+
+    /// Print results in descending order
+    #[clap(long, value_name = "SORTBY", conflicts_with = "sort", value_parser = clap::builder::EnumValueParser::<SortBy>::new())]
+    pub sortr: Option<SortBy>,
+
+    /// Don't print errors for individual files (e.g. permission denied). They still affect the exit code
+    #[clap(short = 's', long)]
+    pub no_messages: bool,
+
+    /// Print statistics for each run: files walked, filtered and searched, bytes searched, matches, errors and stage timings
+    #[clap(long)]
+    pub stats: bool,
+
+    /// When to memory map files. `auto` reads files which could change while being searched (e.g. live logs) into a buffer
+    #[clap(long, default_value = "auto", value_name = "WHEN", value_parser = clap::builder::EnumValueParser::<MmapChoice>::new())]
+    pub mmap: MmapChoice,
+
+    /// Threads to search with, defaults to the number of CPUs. The threadpool's single thread run ignores this
+    #[clap(short = 'j', long, value_name = "NUM", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub threads: Option<usize>,
+
+    /// Results queued for the printer before searching threads wait for it to catch up
+    #[clap(long, default_value_t = tuning::DEFAULT_CHANNEL_CAPACITY, value_name = "NUM")]
+    pub channel_capacity: usize,
+
+    /// Bytes of output buffered before writing to stdout
+    #[clap(long, default_value_t = output::DEFAULT_BUFFER_CAPACITY, value_name = "BYTES")]
+    pub output_buffer: usize,
+
+    /// Flush output once this many bytes are buffered, so results appear during long searches
+    #[clap(long, default_value_t = output::DEFAULT_FLUSH_THRESHOLD, value_name = "BYTES")]
+    pub flush_threshold: usize,
 }
 
 impl std::fmt::Display for Cli {
@@ -224,51 +433,142 @@ pub fn get_cpuworkers() -> usize {
     std::thread::available_parallelism().map_or(4, |n| n.get())
 }
 
+/**
+ * Everything a strategy searches with, set up once from the CLI.
+ *
+ * Patterns are compiled and the inputs resolved here, so every strategy fails the same way for
+ * the same arguments. How the walk is consumed is up to the strategy, see `Walk`.
+ */
+pub struct Search {
+    walk: Walk,
+    matcher: Matcher,
+    output: Output,
+    stats: Arc<Stats>,
+    sort: Sort,
+    mmap: MmapChoice,
+    tuning: Tuning,
+    cancel: CancellationToken,
+}
+
+impl Search {
+    pub fn from_cli(args: &Cli, cancel: CancellationToken) -> Result<Self, MyErrors> {
+        let path_pattern =
+            my_regex::clean_up_regex(args.path_pattern.as_deref(), args.path_pattern_regex)?;
+        let matcher = my_regex::clean_up_regex(Some(&args.file_pattern), args.file_pattern_regex)?
+            .ok_or(MyErrors::MissingPattern)?;
+        let sort = Sort::from_cli(args);
+
+        Ok(Search {
+            walk: Walk {
+                inputs: input::resolve_inputs(args)?,
+                path_pattern,
+                search_archives: args.search_archives,
+                sort,
+            },
+            matcher,
+            output: Output::from_cli(args),
+            stats: Arc::new(Stats::from_cli(args)),
+            sort,
+            mmap: args.mmap,
+            tuning: Tuning::from_cli(args),
+            cancel,
+        })
+    }
+
+    /// The same search with `threads` in place of `--threads`.
+    pub fn with_threads(self, threads: usize) -> Self {
+        Search {
+            tuning: Tuning {
+                threads,
+                ..self.tuning
+            },
+            ..self
+        }
+    }
+}
+
+/// The files to search, walked as an iterator, a parallel iterator or a stream.
+struct Walk {
+    inputs: Vec<Input>,
+    path_pattern: Option<Matcher>,
+    search_archives: bool,
+    sort: Sort,
+}
+
+impl Walk {
+    fn files(
+        self,
+        stats: &Arc<Stats>,
+        cancel: &CancellationToken,
+    ) -> impl Iterator<Item = FileInfo> + Send + use<> {
+        find_files(
+            self.inputs,
+            self.path_pattern,
+            self.search_archives,
+            self.sort,
+            Arc::clone(stats),
+            cancel.clone(),
+        )
+    }
+
+    fn par_files(
+        self,
+        stats: &Arc<Stats>,
+        cancel: &CancellationToken,
+    ) -> impl ParallelIterator<Item = (usize, FileInfo)> + use<> {
+        rayon_find_files(
+            self.inputs,
+            self.path_pattern,
+            self.search_archives,
+            self.sort,
+            Arc::clone(stats),
+            cancel.clone(),
+        )
+    }
+
+    fn stream(
+        self,
+        stats: &Arc<Stats>,
+        cancel: &CancellationToken,
+        capacity: usize,
+    ) -> impl Stream<Item = Result<FileInfo, MyErrors>> + Send + use<> {
+        async_find_files(
+            self.inputs,
+            self.path_pattern,
+            self.search_archives,
+            self.sort,
+            Arc::clone(stats),
+            cancel.clone(),
+            capacity,
+        )
+    }
+}
+
 #[inline]
 pub fn single_thread_read_files<P: Printable>(
     args: Arc<Cli>,
     print_behaviour: P,
-) -> Result<(), MyErrors> {
-    let path_pattern =
-        my_regex::clean_up_regex(args.path_pattern.as_deref(), args.path_pattern_regex)?;
-    let iterator = find_files(&args.path, path_pattern);
-    let file_pattern_re =
-        my_regex::clean_up_regex(Some(&args.file_pattern), args.file_pattern_regex)?.ok_or(
-            MyErrors::SomeError("'file_pattern' is expected to exist".to_string()),
-        )?;
-    use_single_thread(iterator, &file_pattern_re, print_behaviour)?;
-    Ok(())
+    cancel: CancellationToken,
+) -> Result<Outcome, MyErrors> {
+    use_single_thread(Search::from_cli(&args, cancel)?, print_behaviour)
 }
 
 #[inline]
-pub fn rayon_read_files<P: Printable>(args: Arc<Cli>, print_behaviour: P) -> Result<(), MyErrors> {
-    let path_pattern =
-        my_regex::clean_up_regex(args.path_pattern.as_deref(), args.path_pattern_regex)?;
-    let rayon_iterator = rayon_find_files(&args.path, path_pattern);
-    let file_pattern_re =
-        my_regex::clean_up_regex(Some(&args.file_pattern), args.file_pattern_regex)?.ok_or(
-            MyErrors::SomeError("'file_pattern' is expected to exist".to_string()),
-        )?;
-    use_rayon(rayon_iterator, &file_pattern_re, print_behaviour)?;
-
-    Ok(())
+pub fn rayon_read_files<P: Printable>(
+    args: Arc<Cli>,
+    print_behaviour: P,
+    cancel: CancellationToken,
+) -> Result<Outcome, MyErrors> {
+    use_rayon(Search::from_cli(&args, cancel)?, print_behaviour)
 }
 
 #[inline]
 pub fn thread_per_file_read_files<P: Printable>(
     args: Arc<Cli>,
     print_behaviour: P,
-) -> Result<(), MyErrors> {
-    let path_pattern =
-        my_regex::clean_up_regex(args.path_pattern.as_deref(), args.path_pattern_regex)?;
-    let iterator = find_files(&args.path, path_pattern);
-    let file_pattern_re =
-        my_regex::clean_up_regex(Some(&args.file_pattern), args.file_pattern_regex)?.ok_or(
-            MyErrors::SomeError("'file_pattern' is expected to exist".to_string()),
-        )?;
-    use_thread_per_file(iterator, &file_pattern_re, print_behaviour)?;
-
-    Ok(())
+    cancel: CancellationToken,
+) -> Result<Outcome, MyErrors> {
+    use_thread_per_file(Search::from_cli(&args, cancel)?, print_behaviour)
 }
 
 #[inline]
@@ -276,60 +576,148 @@ pub fn threadpool_read_files<P: Printable>(
     args: Arc<Cli>,
     print_behaviour: P,
     number_of_workers: usize,
-) -> Result<(), MyErrors> {
-    let path_pattern =
-        my_regex::clean_up_regex(args.path_pattern.as_deref(), args.path_pattern_regex)?;
-    let iterator = find_files(&args.path, path_pattern);
-    let file_pattern_re =
-        my_regex::clean_up_regex(Some(&args.file_pattern), args.file_pattern_regex)?.ok_or(
-            MyErrors::SomeError("'file_pattern' is expected to exist".to_string()),
-        )?;
-    use_thread_pool::<_, _, { 256 * 1024 }>(
-        iterator,
-        &file_pattern_re,
+    cancel: CancellationToken,
+) -> Result<Outcome, MyErrors> {
+    use_thread_pool(
+        Search::from_cli(&args, cancel)?.with_threads(number_of_workers),
         print_behaviour,
-        number_of_workers,
-    )?;
-
-    Ok(())
+    )
 }
 
-/// TODO: examine iterator, likely add async friendly iterator instead of forcing existing to work.
 pub async fn async_read_files<P: Printable>(
     args: Arc<Cli>,
     print_behaviour: P,
-) -> Result<(), MyErrors> {
-    let path_pattern =
-        my_regex::clean_up_regex(args.path_pattern.as_deref(), args.path_pattern_regex)?;
-    let iterator = find_files(&args.path, path_pattern);
-    let file_pattern_re =
-        my_regex::clean_up_regex(Some(&args.file_pattern), args.file_pattern_regex)?.ok_or(
-            MyErrors::SomeError("'file_pattern' is expected to exist".to_string()),
-        )?;
-    use_async_two::<_, _>(iterator, &file_pattern_re, print_behaviour).await?;
+    cancel: CancellationToken,
+) -> Result<Outcome, MyErrors> {
+    use_async_two(Search::from_cli(&args, cancel)?, print_behaviour).await
+}
 
-    Ok(())
+/**
+ * Searches as `async_read_files` does, handing each file with matches to the caller rather than printing it.
+ *
+ * Only `--threads` files are searched ahead of whoever is consuming the stream, and the walk stops
+ * once `--channel-capacity` files are waiting, so a slow consumer slows the search rather than
+ * results building up in memory. Dropping the stream cancels the search, searches already running
+ * finish in the background but nothing more is walked or searched.
+ *
+ * Results follow `--sort`/`--sortr` when given, otherwise they arrive as each file is searched.
+ * An error for a single file, including a path which couldn't be walked or an archive member which
+ * couldn't be read, is yielded without ending the stream. Nothing is written to stderr. An error
+ * setting up the search (e.g. an invalid pattern) is the stream's only item.
+ */
+pub async fn search_stream(
+    args: Arc<Cli>,
+) -> impl Stream<Item = Result<FileMatches, MyErrors>> + Send + 'static {
+    let cancel = CancellationToken::new();
+    let search = match Search::from_cli(&args, cancel.clone()) {
+        // Errors are yielded to the caller rather than written to stderr
+        Ok(search) => Search {
+            stats: Arc::new(Stats::new(true)),
+            ..search
+        },
+        Err(err) => return Either::Left(stream::once(future::ready(Err(err)))),
+    };
+
+    let guard = cancel.drop_guard();
+    let sort = search.sort;
+    let mut reorder = ReorderBuffer::default();
+    Either::Right(
+        search_async(search)
+            .map(move |searched| {
+                // Owned by the stream, so dropping the stream cancels the search
+                let _guard = &guard;
+
+                let (found, err) = match searched.found {
+                    Ok(found) => (found, None),
+                    Err(err) => (None, Some(Err(err))),
+                };
+                if !sort.is_ordered() {
+                    return err.into_iter().chain(found.map(Ok)).collect();
+                }
+
+                reorder.push(searched.seq, found);
+                err.into_iter()
+                    .chain(std::iter::from_fn(|| reorder.pop()).map(Ok))
+                    .collect::<Vec<_>>()
+            })
+            .flat_map(stream::iter),
+    )
 }
 
 struct FileInfo {
     path: PathBuf,
     filename: String,
+    /// Already decompressed contents of an archive member, searched instead of reading `path`.
+    contents: Option<Vec<u8>>,
 }
 
 impl FileInfo {
-    fn get_identifier(&self) -> String {
-        format!("{}", &self)
+    fn new(path: &Path, filename: &str) -> Self {
+        FileInfo {
+            path: path.to_path_buf(),
+            filename: filename.to_string(),
+            contents: None,
+        }
+    }
+}
+
+/// A file with at least one matching line, produced by every strategy.
+#[derive(Clone, Debug)]
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub filename: String,
+    pub lines: Vec<LineMatch>,
+    /// Length of the file, for the JSON `end` message
+    pub bytes_searched: u64,
+    /// Time spent searching the file, also for the JSON `end` message
+    pub elapsed: Duration,
+}
+
+impl FileMatches {
+    fn new(
+        file: &FileInfo,
+        lines: Vec<LineMatch>,
+        bytes_searched: usize,
+        elapsed: Duration,
+    ) -> Self {
+        FileMatches {
+            path: file.path.clone(),
+            filename: file.filename.clone(),
+            lines,
+            bytes_searched: bytes_searched as u64,
+            elapsed,
+        }
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.lines.iter().map(|line| line.submatches.len()).sum()
     }
 }
 
-impl fmt::Display for FileInfo {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = Colour::Green.paint(format!("{} {:?}", self.filename, self.path));
-        // let s = Style::new().bold().paint(format!("Path: {:?}, Filename: {}", self.path, filename));
-        write!(f, "{}", s)
+/// Ignores `elapsed`, which differs every run, so the strategies' results can be compared.
+impl PartialEq for FileMatches {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.filename == other.filename
+            && self.lines == other.lines
+            && self.bytes_searched == other.bytes_searched
     }
 }
 
+/// A single line containing one or more matches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineMatch {
+    /// Starts at 1
+    pub line_number: usize,
+    /// Offset of the start of the line from the start of the file
+    pub byte_offset: usize,
+    /// The line, excluding its line terminator
+    pub line: Vec<u8>,
+    pub terminator: LineTerminator,
+    /// Byte ranges of each match within `line`
+    pub submatches: Vec<Range<usize>>,
+}
+
 // /**
 //  * Use a single initialised re pattern to save it being created on each call (STAR_PATTERN)
 //  *
@@ -347,475 +735,920 @@ impl fmt::Display for FileInfo {
 //         .transpose()
 // }
 
-fn information_out(results: &Vec<(String, Vec<String>)>) -> Result<(), MyErrors> {
-    let found_matches_count = results.len();
-    let mut handle = BufWriter::new(io::stdout());
-    writeln!(handle, "\nFound {} files", found_matches_count).map_err(MyErrors::FileIO)?;
-    for (f, r) in results {
-        writeln!(handle, "Filename found with matches: {}", f).map_err(MyErrors::FileIO)?;
-        for m in r {
-            writeln!(handle, "{}", m).map_err(MyErrors::FileIO)?;
+/**
+ * Writes results as each file completes, shared by every strategy.
+ *
+ * Results are pushed with the sequence number of their file within the walk. Without sorting they're
+ * written immediately, otherwise they're held in a `ReorderBuffer` until every earlier file is done.
+ */
+struct StreamPrinter<P: Printable, W: Write> {
+    handle: BufWriter<W>,
+    print_behaviour: P,
+    output: Output,
+    sort: Sort,
+    reorder: ReorderBuffer<FileMatches>,
+    summary: Summary,
+    stats: Arc<Stats>,
+    cancel: CancellationToken,
+}
+
+impl<P: Printable, W: Write> StreamPrinter<P, W> {
+    fn new(
+        writer: W,
+        print_behaviour: P,
+        output: Output,
+        stats: Arc<Stats>,
+        sort: Sort,
+        cancel: CancellationToken,
+    ) -> Result<Self, MyErrors> {
+        let mut handle = BufWriter::with_capacity(output.buffer_capacity, writer);
+        if !output.is_machine_readable() {
+            print_behaviour
+                .writeln_w_handler(&mut handle, |h| writeln!(h).map_err(MyErrors::Output))?;
         }
+
+        Ok(StreamPrinter {
+            handle,
+            print_behaviour,
+            output,
+            sort,
+            reorder: ReorderBuffer::default(),
+            summary: Summary::default(),
+            stats,
+            cancel,
+        })
     }
 
-    handle.flush().map_err(MyErrors::FileIO)?;
-    Ok(())
+    fn push(&mut self, seq: usize, found: Option<FileMatches>) -> Result<(), MyErrors> {
+        if !self.sort.is_ordered() {
+            return match found {
+                Some(found) => self.write(found),
+                None => Ok(()),
+            };
+        }
+
+        self.reorder.push(seq, found);
+        while let Some(found) = self.reorder.pop() {
+            self.write(found)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, found: FileMatches) -> Result<(), MyErrors> {
+        self.summary.add(&found);
+        let output = self.output;
+        let stats = Arc::clone(&self.stats);
+        stats.time(Stage::Print, || {
+            self.print_behaviour
+                .information_out(&mut self.handle, found, |h, xx| {
+                    information_out_each_lock(h, &xx, &output, &stats)
+                })
+        })
+    }
+
+    /// Writes the footer and any errors, returning what the run found.
+    /// A cancelled run always writes its (partial) stats, so it's clear how far it got.
+    ///
+    /// Results still held are waiting on a file which never sent one, they're written out rather
+    /// than dropped and the gap is recorded as an error.
+    fn finish(mut self) -> Result<Outcome, MyErrors> {
+        let (missing, held) = self.reorder.drain();
+        if missing > 0 {
+            self.stats.error(
+                Path::new(""),
+                &MyErrors::ThreadPanic(format!(
+                    "{} file(s) never sent a result, later results were printed without them",
+                    missing
+                )),
+            );
+        }
+        for found in held {
+            self.write(found)?;
+        }
+
+        let output = self.output;
+        let summary = self.summary;
+        let cancelled = self.cancel.is_cancelled();
+        let report = self.stats.report(&summary);
+        let with_stats = output.stats || cancelled;
+        self.print_behaviour
+            .writeln_w_handler(&mut self.handle, |h: &mut BufWriter<W>| {
+                if !output.is_machine_readable() {
+                    writeln!(h, "Found {} files", summary.searches_with_match)
+                        .map_err(MyErrors::Output)?;
+                }
+
+                output
+                    .write_summary(h, &report, with_stats)
+                    .map_err(MyErrors::Output)
+            })?;
+        self.handle.flush().map_err(MyErrors::Output)?;
+
+        let stats = Arc::clone(&self.stats);
+        self.print_behaviour
+            .writeln_w_handler(&mut BufWriter::new(io::stderr()), |h| {
+                if cancelled {
+                    writeln!(h, "Cancelled, results are partial").map_err(MyErrors::Output)?;
+                }
+                stats.write_errors(h).map_err(MyErrors::Output)
+            })?;
+
+        Ok(Outcome {
+            cancelled,
+            ..self.stats.outcome(&summary)
+        })
+    }
 }
 
-/// Based on various suggestions - Matches common filesystem block sizes // 64KB
-fn information_out_each_lock_default(
-    handle: &mut BufWriter<StdoutLock>,
-    results: &(String, Vec<String>),
-) -> Result<(), MyErrors> {
-    information_out_each_lock::<{ 64 * 1024 }>(handle, results)
+/// A dedicated printer thread for the strategies which search on several threads at once.
+fn spawn_printer<P: Printable>(
+    rx: crossbeam_channel::Receiver<(usize, Option<FileMatches>)>,
+    print_behaviour: P,
+    output: Output,
+    stats: Arc<Stats>,
+    sort: Sort,
+    cancel: CancellationToken,
+) -> thread::JoinHandle<Result<Outcome, MyErrors>> {
+    thread::spawn(move || {
+        let mut printer = StreamPrinter::new(
+            io::stdout().lock(),
+            print_behaviour,
+            output,
+            stats,
+            sort,
+            cancel,
+        )?;
+        while let Ok((seq, found)) = rx.recv() {
+            printer.push(seq, found)?;
+        }
+        printer.finish()
+    })
 }
 
-fn information_out_each_lock<const FLUSH_THRESHOLD: usize>(
-    handle: &mut BufWriter<StdoutLock>,
-    results: &(String, Vec<String>),
-) -> Result<(), MyErrors> {
-    let (file, r) = results;
-    writeln!(handle, "Filename found with matches: {}", file).map_err(MyErrors::FileIO)?;
-    for m in r {
-        writeln!(handle, "{}", m).map_err(MyErrors::FileIO)?;
+/// Sends a file's result to the printer thread.
+/// Files without matches are only needed when ordering, so the printer can move past them.
+/// The printer only stops early when writing fails, the file's results are then reported as lost.
+fn send_found(
+    tx: &crossbeam_channel::Sender<(usize, Option<FileMatches>)>,
+    stats: &Stats,
+    sort: Sort,
+    seq: usize,
+    path: &Path,
+    found: Option<FileMatches>,
+) {
+    if (found.is_some() || sort.is_ordered())
+        && let Err(e) = tx.send((seq, found))
+    {
+        let err = io::Error::new(io::ErrorKind::BrokenPipe, e.to_string());
+        stats.error(path, &MyErrors::Output(err));
+    }
+}
+
+/// Searches a single file, reporting (rather than returning) any error.
+/// Files still queued when the run is cancelled are skipped.
+fn search_file(
+    file: &FileInfo,
+    matcher: &Matcher,
+    chunker: &Chunker,
+    stats: &Stats,
+    mmap: MmapChoice,
+    cancel: &CancellationToken,
+) -> Option<FileMatches> {
+    if cancel.is_cancelled() {
+        return None;
     }
+
+    match stats.time(Stage::Search, || {
+        find_entry_with_file_memmap(file, matcher, chunker, stats, mmap)
+    }) {
+        Err(err) => {
+            stats.error(&file.path, &err);
+            None
+        }
+        Ok(found) if found.lines.is_empty() => None,
+        Ok(found) => Some(found),
+    }
+}
+
+fn information_out_each_lock<W: Write>(
+    handle: &mut BufWriter<W>,
+    results: &FileMatches,
+    output: &Output,
+    stats: &Stats,
+) -> Result<(), MyErrors> {
+    let printed = output
+        .write_file(handle, results)
+        .map_err(MyErrors::Output)?;
+    stats.printed(printed);
     // periodic flushing.
-    if handle.buffer().len() > FLUSH_THRESHOLD {
-        handle.flush().map_err(MyErrors::FileIO)?;
+    if handle.buffer().len() > output.flush_threshold {
+        handle.flush().map_err(MyErrors::Output)?;
     }
 
     Ok(())
 }
 
-async fn use_async_two<I, P: Printable>(
-    iterator: I,
-    re: &Regex,
+/**
+ * Searches files as the walk finds them, `tuning.threads` at a time.
+ *
+ * Reading is async and the search itself runs on tokio's blocking threads, as it's CPU bound.
+ * Results are printed on their own thread, as the threaded strategies do, so no runtime worker
+ * blocks on stdout.
+ * With the `io_uring` feature files are instead read ahead in batches, see `uring::read_ahead`.
+ * Files are searched as bytes, so a file which isn't valid UTF-8 is searched (and its lines printed
+ * lossily) rather than reported as an error.
+ */
+pub(crate) async fn use_async_two<P: Printable>(
+    search: Search,
     print_behaviour: P,
-) -> Result<(), MyErrors>
-where
-    I: Iterator<Item = FileInfo>,
-{
-    let results = stream::iter(iterator)
-        .map(|f: FileInfo| {
-            let identifier = f.get_identifier();
-            let path = f.path.clone();
-            let re_copy = re.clone();
+) -> Result<Outcome, MyErrors> {
+    let stats = Arc::clone(&search.stats);
+    let sort = search.sort;
+    let (tx, rx) = crossbeam_channel::bounded(search.tuning.channel_capacity);
+    let print_handle = spawn_printer(
+        rx,
+        print_behaviour,
+        search.output,
+        Arc::clone(&stats),
+        sort,
+        search.cancel.clone(),
+    );
+
+    let mut results = std::pin::pin!(search_async(search));
+    while let Some(searched) = results.next().await {
+        let found = searched.found.unwrap_or_else(|err| {
+            stats.error(&searched.path, &err);
+            None
+        });
+        // The channel is bounded, so waiting on a slow printer is kept off the runtime's workers
+        let (tx, stats) = (tx.clone(), Arc::clone(&stats));
+        tokio::task::spawn_blocking(move || {
+            send_found(&tx, &stats, sort, searched.seq, &searched.path, found)
+        })
+        .await
+        .map_err(MyErrors::TokioError)?;
+    }
+    drop(tx);
+
+    tokio::task::spawn_blocking(move || print_handle.join())
+        .await
+        .map_err(MyErrors::TokioError)?
+        .map_err(|err| MyErrors::ThreadPanic(format!("{:?}", err)))?
+}
+
+/// A file searched by `search_async`, along with its place in the walk.
+struct Searched {
+    seq: usize,
+    path: PathBuf,
+    /// An error for just this file (including walking to it), which shouldn't stop the rest of the search
+    found: Result<Option<FileMatches>, MyErrors>,
+}
+
+/**
+ * The async strategy's search, without printing. Shared by `use_async_two` and `search_stream`.
+ *
+ * Results arrive as searches finish rather than in walk order. Files are only pulled from the walk
+ * as searches finish too, so a slow consumer slows the walk rather than results piling up.
+ * Nothing is reported to `stats` as an error, errors are left to the caller.
+ */
+fn search_async(search: Search) -> impl Stream<Item = Searched> + Send + use<> {
+    let Search {
+        walk,
+        matcher,
+        stats,
+        tuning,
+        cancel,
+        ..
+    } = search;
+    let files = walk.stream(&stats, &cancel, tuning.channel_capacity);
+    #[cfg(all(feature = "io_uring", target_os = "linux"))]
+    let files = uring::read_ahead(files);
+
+    let matcher = Arc::new(matcher);
+    let chunker = Arc::new(Chunker::new(tuning.threads));
+    files
+        .enumerate()
+        .map(move |(seq, file)| {
+            let matcher = Arc::clone(&matcher);
+            let chunker = Arc::clone(&chunker);
+            let stats = Arc::clone(&stats);
+            let cancel = cancel.clone();
 
             async move {
-                // // Option 1
-                // let file = tokio::fs::File::open(path)
-                //     .await
-                //     .map_err(MyErrors::FileIO)?;
-                // let reader = tokio::io::BufReader::new(file);
-                // let mut lines = reader.lines();
-
-                // let mut found: Vec<String> = Vec::new();
-                // let mut idx = 0;
-                // while let Some(line) = lines.next_line().await.map_err(MyErrors::FileIO)? {
-
-                //     let re_copy = re.clone();
-                //     idx += 1;
-
-                //     let inner_found = tokio::task::spawn_blocking(move || {
-                //         let replaced = re_copy.replace_all(&line, |caps: &regex::Captures| {
-                //             Colour::Red.paint(&caps[0]).to_string()
-                //         });
-
-                //         if let Cow::Owned(_) = replaced {
-                //             Some(format!(
-                //                 "{}:{}",
-                //                 Colour::Green.paint(format!("{}", idx)),
-                //                 replaced
-                //             ))
-                //         } else {
-                //             None
-                //         }
-                //     })
-                //     .await
-                //     .map_err(MyErrors::TokioError)?;
-
-                //     if let Some(line) = inner_found {
-                //         found.push(line);
-                //     }
-                // }
-
-                // Option 2
-                let buffer = tokio::fs::read(path).await.map_err(MyErrors::FileIO)?;
-                let found: Vec<String> = tokio::task::spawn_blocking(
-                    // useful when expecting a task/s which ARE CPU bound
-                    move || -> Result<Vec<String>, MyErrors> {
-                        let contents = String::from_utf8(buffer).map_err(MyErrors::Utf8Error)?;
-                        let found = contents
-                            .lines()
-                            .enumerate()
-                            .filter_map(|(idx, line)| -> Option<String> {
-                                let replaced =
-                                    re_copy.replace_all(line, |caps: &regex::Captures| {
-                                        Colour::Red.paint(&caps[0]).to_string()
-                                    });
-
-                                if let Cow::Owned(_) = replaced {
-                                    Some(format!(
-                                        "{}:{}",
-                                        Colour::Green.paint(format!("{}", idx + 1)),
-                                        replaced
-                                    ))
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect::<Vec<String>>();
-
-                        Ok(found)
-                    },
-                )
-                .await
-                .map_err(MyErrors::TokioError)??;
-
-                Ok::<(String, Vec<String>), MyErrors>((identifier, found))
-            }
-        })
-        .buffer_unordered(get_cpuworkers()) // controls memory usage by limiting concurrency to something the system can handle
-        .try_filter_map(|result: (String, Vec<String>)| async move {
-            match result {
-                (id, found) if !found.is_empty() => Ok(Some((id, found))),
-                _ => Ok(None),
+                let mut f = match file {
+                    Ok(file) => file,
+                    // A path or archive member which couldn't be walked, so has nothing to search
+                    Err(err) => {
+                        return Searched {
+                            seq,
+                            path: err.path().map(Path::to_path_buf).unwrap_or_default(),
+                            found: Err(err),
+                        };
+                    }
+                };
+                let path = f.path.clone();
+                let contents = f.contents.take();
+
+                // Futures already buffered when cancelled skip their file
+                if cancel.is_cancelled() {
+                    return Searched {
+                        seq,
+                        path,
+                        found: Ok(None),
+                    };
+                }
+
+                let searched = async {
+                    let buffer = match contents {
+                        Some(contents) => contents,
+                        None => tokio::fs::read(&path)
+                            .await
+                            .map_err(|e| MyErrors::io(&path, Operation::Read, e))?,
+                    };
+                    let bytes_searched = buffer.len();
+                    stats.searched(bytes_searched);
+                    let search_stats = Arc::clone(&stats);
+                    let (found, elapsed) = tokio::task::spawn_blocking(
+                        // useful when expecting a task/s which ARE CPU bound
+                        move || {
+                            let start = Instant::now();
+                            let found =
+                                find_entry_within_bytes_chunked(&buffer, &matcher, &chunker);
+                            let elapsed = start.elapsed();
+                            search_stats.add_time(Stage::Search, elapsed);
+                            (found, elapsed)
+                        },
+                    )
+                    .await
+                    .map_err(MyErrors::TokioError)?;
+
+                    Ok::<FileMatches, MyErrors>(FileMatches::new(
+                        &f,
+                        found,
+                        bytes_searched,
+                        elapsed,
+                    ))
+                }
+                .await;
+
+                let found = match searched {
+                    Err(err) => Err(err),
+                    Ok(found) if found.lines.is_empty() => Ok(None),
+                    Ok(found) => Ok(Some(found)),
+                };
+                Searched {
+                    seq,
+                    path: f.path,
+                    found,
+                }
             }
         })
-        .try_collect::<Vec<(String, Vec<String>)>>()
-        .await?;
-
-    print_behaviour.writeln(results, |r| information_out(&r))?;
-    Ok(())
+        .buffer_unordered(tuning.threads) // controls memory usage by limiting concurrency to something the system can handle
 }
 
 #[allow(dead_code)]
 async fn use_async<I, P: Printable>(
     iterator: I,
-    re: &Regex,
+    matcher: &Matcher,
     print_behaviour: P,
-) -> Result<(), MyErrors>
+    output: Output,
+    stats: Arc<Stats>,
+) -> Result<Outcome, MyErrors>
 where
     I: Iterator<Item = FileInfo>,
 {
-    let results: Vec<(String, Vec<String>)> = stream::iter(iterator)
-        .filter_map(|file| async move {
-            find_entry_with_file_async(&file, re)
-                .await
-                .map_err(|err| {
-                    eprintln!("Error while searching file {}", err);
-                    err
-                })
-                .ok()
-                .filter(|found| !found.is_empty())
-                .map(|found| (file.get_identifier(), found))
-        })
-        .collect()
-        .await;
+    let mut printer = StreamPrinter::new(
+        io::stdout(),
+        print_behaviour,
+        output,
+        Arc::clone(&stats),
+        Sort::default(),
+        CancellationToken::new(),
+    )?;
 
-    print_behaviour.writeln(results, |r| information_out(&r))?;
+    let mut results = std::pin::pin!(stream::iter(iterator).then(|file| async move {
+        find_entry_with_file_async(&file, matcher)
+            .await
+            .map_err(|err| {
+                eprintln!("Error while searching file {}", err);
+                err
+            })
+            .ok()
+            .filter(|found| !found.lines.is_empty())
+    }));
+
+    let mut seq = 0;
+    while let Some(found) = results.next().await {
+        printer.push(seq, found)?;
+        seq += 1;
+    }
 
-    Ok(())
+    printer.finish()
 }
 
 #[allow(dead_code)]
-async fn find_entry_with_file_async(f: &FileInfo, re: &Regex) -> Result<Vec<String>, MyErrors> {
+async fn find_entry_with_file_async(
+    f: &FileInfo,
+    matcher: &Matcher,
+) -> Result<FileMatches, MyErrors> {
+    let start = Instant::now();
     let mut found_lines = Vec::new();
 
     let mut file = tokio::fs::File::open(&f.path)
         .await
-        .map_err(MyErrors::FileIO)?;
+        .map_err(|e| MyErrors::io(&f.path, Operation::Open, e))?;
     let mut buffer = Vec::new();
 
     file.read_to_end(&mut buffer)
         .await
-        .map_err(MyErrors::FileIO)?;
+        .map_err(|e| MyErrors::io(&f.path, Operation::Read, e))?;
 
-    let contents = String::from_utf8(buffer).map_err(MyErrors::Utf8Error)?;
+    let contents = String::from_utf8(buffer).map_err(|source| MyErrors::Utf8Error {
+        path: f.path.clone(),
+        source,
+    })?;
 
     for (idx, line) in contents.lines().enumerate() {
-        let replaced = re.replace_all(line, |caps: &regex::Captures| {
-            Colour::Red.paint(&caps[0]).to_string()
-        });
-
-        if let Cow::Owned(_) = replaced {
-            found_lines.push(format!(
-                "{}:{}",
-                Colour::Green.paint(format!("{}", idx + 1)),
-                replaced
-            ));
+        let offset = offset_within(&contents, line);
+        if let Some(found) = find_in_str_line(
+            matcher.regex(),
+            line,
+            LineTerminator::at(&contents.as_bytes()[offset + line.len()..]),
+            idx + 1,
+            offset,
+        ) {
+            found_lines.push(found);
         }
     }
 
-    Ok(found_lines)
+    Ok(FileMatches::new(
+        f,
+        found_lines,
+        contents.len(),
+        start.elapsed(),
+    ))
 }
 
-fn use_single_thread<I, P: Printable>(
-    iterator: I,
-    re: &Regex,
+pub(crate) fn use_single_thread<P: Printable>(
+    search: Search,
     print_behaviour: P,
-) -> Result<(), MyErrors>
-where
-    I: Iterator<Item = FileInfo>,
-{
-    let results: Vec<(String, Vec<String>)> = iterator
-        .filter_map(|file| match find_entry_with_file_memmap(&file, re) {
-            Err(err) => {
-                eprintln!("Error while searching file {}", err);
-                None
-            }
-            Ok(found) => {
-                if !found.is_empty() {
-                    Some((file.get_identifier(), found))
-                } else {
-                    None
-                }
-            }
-        })
-        .collect();
+) -> Result<Outcome, MyErrors> {
+    let Search {
+        walk,
+        matcher,
+        output,
+        stats,
+        mmap,
+        cancel,
+        ..
+    } = search;
+    let iterator = walk.files(&stats, &cancel);
+
+    // Already in walk order, so there is nothing to reorder
+    let mut printer = StreamPrinter::new(
+        io::stdout().lock(),
+        print_behaviour,
+        output,
+        Arc::clone(&stats),
+        Sort::default(),
+        cancel.clone(),
+    )?;
 
-    print_behaviour.writeln(results, |r| information_out(&r))?;
+    // Large files aren't split either, everything stays on this thread
+    let chunker = Chunker::new(1);
+    for (seq, file) in iterator.enumerate() {
+        printer.push(
+            seq,
+            search_file(&file, &matcher, &chunker, &stats, mmap, &cancel),
+        )?;
+    }
 
-    Ok(())
+    printer.finish()
 }
 
 /**
- * This is the initial implementation using thread::spawn
+ * This is the initial implementation using thread::spawn, one thread per file.
+ *
+ * At most `tuning.threads` run at once, the walk waits for one to finish before spawning another, so
+ * a large tree can't exhaust the OS's threads. With the same cap as the threadpool this measures
+ * the cost of spawning a thread per file rather than reusing them.
+ *
+ * A panicking file is reported as a `MyErrors::ThreadPanic` against its path and the search
+ * carries on.
  */
-fn use_thread_per_file<I, P: Printable>(
-    iterator: I,
-    re: &Regex,
+pub(crate) fn use_thread_per_file<P: Printable>(
+    search: Search,
     print_behaviour: P,
-) -> Result<(), MyErrors>
-where
-    I: Iterator<Item = FileInfo>,
-{
-    let matched_paths = iterator.collect::<Vec<FileInfo>>();
-
-    let mut handles = Vec::new();
-    let re = Arc::new(re.to_owned());
-    for file in matched_paths {
-        let re: Arc<Regex> = Arc::clone(&re);
-        let file_id = file.get_identifier();
-        let handle: thread::JoinHandle<Vec<String>> =
-            thread::spawn(move || match find_entry_with_file_memmap(&file, &re) {
-                Err(err) => {
-                    eprintln!("Error while searching file {}", err);
-                    Vec::new()
-                }
-                Ok(found) => found,
-            });
+) -> Result<Outcome, MyErrors> {
+    let Search {
+        walk,
+        matcher,
+        output,
+        stats,
+        sort,
+        mmap,
+        tuning,
+        cancel,
+    } = search;
+    let iterator = walk.files(&stats, &cancel);
+
+    let (tx, rx) = crossbeam_channel::bounded(tuning.channel_capacity);
+    let print_handle = spawn_printer(
+        rx,
+        print_behaviour,
+        output,
+        Arc::clone(&stats),
+        sort,
+        cancel.clone(),
+    );
+
+    let max_threads = tuning.threads.max(1);
+    let mut running = VecDeque::with_capacity(max_threads);
+    let matcher = Arc::new(matcher);
+    let chunker = Arc::new(Chunker::new(max_threads));
+    for (seq, file) in iterator.enumerate() {
+        if running.len() == max_threads {
+            // Free a slot, preferring a thread that has already finished over waiting on the oldest
+            let slot = running
+                .iter()
+                .position(|(_, _, handle): &SpawnedSearch| handle.is_finished())
+                .unwrap_or(0);
+            finished_search(&tx, sort, &stats, running.remove(slot).unwrap());
+        }
+
+        let path = file.path.clone();
+        let matcher = Arc::clone(&matcher);
+        let chunker = Arc::clone(&chunker);
+        let stats = Arc::clone(&stats);
+        let cancel = cancel.clone();
+        let tx = tx.clone();
+        let handle: thread::JoinHandle<()> = thread::spawn(move || {
+            let found = search_file(&file, &matcher, &chunker, &stats, mmap, &cancel);
+            send_found(&tx, &stats, sort, seq, &file.path, found)
+        });
 
-        handles.push((file_id, handle));
+        running.push_back((seq, path, handle));
     }
 
-    let results = handles
-        .into_iter()
-        .map(|f| (f.0, f.1.join().unwrap()))
-        .filter(|result| !result.1.is_empty())
-        .collect::<Vec<(String, _)>>();
+    for search in running {
+        finished_search(&tx, sort, &stats, search);
+    }
+    drop(tx);
+    print_handle
+        .join()
+        .map_err(|err| MyErrors::ThreadPanic(format!("{:?}", err)))?
+}
 
-    print_behaviour.writeln(results, |r| information_out(&r))?;
+/// A file being searched by `use_thread_per_file`: its sequence number, path and thread.
+type SpawnedSearch = (usize, PathBuf, thread::JoinHandle<()>);
+
+/// Joins a search's thread. A panic never sent the file's result, so its place in the output
+/// is filled with nothing (otherwise a sorted printer would wait on it forever).
+fn finished_search(
+    tx: &crossbeam_channel::Sender<(usize, Option<FileMatches>)>,
+    sort: Sort,
+    stats: &Stats,
+    (seq, path, handle): SpawnedSearch,
+) {
+    if let Err(payload) = handle.join() {
+        stats.error(&path, &MyErrors::ThreadPanic(panic_message(&*payload)));
+        send_found(tx, stats, sort, seq, &path, None);
+    }
+}
 
-    Ok(())
+/// The message a thread panicked with, `panic!` payloads are either a `&str` or a `String`.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
 }
 
-///
-/// Fits in L2 cache (most modern CPUs)
-///
-fn use_thread_pool<I, P: Printable, const BUF_CAPACITY: usize>(
-    iterator: I,
-    re: &Regex,
+/// A fixed set of `tuning.threads` workers, each file is queued as a job.
+/// A panicking job is reported against its file, as with `use_thread_per_file`.
+pub(crate) fn use_thread_pool<P: Printable>(
+    search: Search,
     print_behaviour: P,
-    number_of_workers: usize,
-) -> Result<(), MyErrors>
-where
-    I: Iterator<Item = FileInfo>,
-{
-    let pool = ThreadPool::new(number_of_workers);
-    let re = Arc::new(re.to_owned());
-
-    let (tx, rx) = crossbeam_channel::bounded(1000);
-    let files_found_matching_file_regex = Arc::new(AtomicUsize::new(0));
-
-    let print_handle = thread::spawn(move || -> Result<_, MyErrors> {
-        let stdout = io::stdout();
-        let mut handle = BufWriter::with_capacity(BUF_CAPACITY, stdout.lock()); // 1MB buffer
-
-        print_behaviour
-            .writeln_w_handler(&mut handle, |h| writeln!(h).map_err(MyErrors::FileIO))?;
-
-        while let Ok(x) = rx.recv() {
-            // TODO: Investigate the "Ordering" stuff more.
-            files_found_matching_file_regex.fetch_add(1, Ordering::Relaxed);
-            print_behaviour.information_out(&mut handle, x, |h, xx| {
-                information_out_each_lock_default(h, &xx)
-            })?;
-        }
-
-        print_behaviour.writeln_w_handler(&mut handle, |h: &mut BufWriter<StdoutLock<'_>>| {
-            writeln!(
-                h,
-                "Found {} files",
-                files_found_matching_file_regex.load(Ordering::Relaxed)
-            )
-            .map_err(MyErrors::FileIO)
-        })?;
-
-        handle.flush().map_err(MyErrors::FileIO)?;
-        Ok(())
-    });
-
-    iterator.for_each(|file| {
-        let tx: crossbeam_channel::Sender<(String, Vec<String>)> = tx.clone();
-        let re: Arc<Regex> = Arc::clone(&re);
-        let file_id = file.get_identifier();
-
-        pool.execute(move || match find_entry_with_file_memmap(&file, &re) {
-            Err(err) => {
-                eprintln!("Error while searching file {}", err);
-            }
-            Ok(found) => {
-                if !found.is_empty() {
-                    if let Err(e) = tx.send((file_id, found)) {
-                        eprintln!(
-                            "Critical error while handling successful file internal search: {}",
-                            e
-                        )
-                    }
-                }
-            }
+) -> Result<Outcome, MyErrors> {
+    let Search {
+        walk,
+        matcher,
+        output,
+        stats,
+        sort,
+        mmap,
+        tuning,
+        cancel,
+    } = search;
+    let iterator = walk.files(&stats, &cancel);
+
+    let pool = ThreadPool::new(tuning.threads);
+    let matcher = Arc::new(matcher);
+    // With a single worker large files aren't split either
+    let chunker = Arc::new(Chunker::new(tuning.threads));
+
+    let (tx, rx) = crossbeam_channel::bounded(tuning.channel_capacity);
+    let print_handle = spawn_printer(
+        rx,
+        print_behaviour,
+        output,
+        Arc::clone(&stats),
+        sort,
+        cancel.clone(),
+    );
+
+    iterator.enumerate().for_each(|(seq, file)| {
+        let tx: crossbeam_channel::Sender<(usize, Option<FileMatches>)> = tx.clone();
+        let matcher = Arc::clone(&matcher);
+        let chunker = Arc::clone(&chunker);
+        let stats = Arc::clone(&stats);
+        let cancel = cancel.clone();
+
+        // Queued files are skipped by `search_file` once cancelled, so the pool drains quickly
+        pool.execute(move || {
+            // As with `finished_search`, a panic is reported against the file and fills its place
+            let path = file.path.clone();
+            let found = panic::catch_unwind(AssertUnwindSafe(|| {
+                search_file(&file, &matcher, &chunker, &stats, mmap, &cancel)
+            }))
+            .unwrap_or_else(|payload| {
+                stats.error(&path, &MyErrors::ThreadPanic(panic_message(&*payload)));
+                None
+            });
+            send_found(&tx, &stats, sort, seq, &path, found)
         });
     });
 
     drop(tx);
-    print_handle
+    let outcome = print_handle
         .join()
         .map_err(|err| MyErrors::ThreadPanic(format!("{:?}", err)))??;
     pool.join();
 
-    Ok(())
+    Ok(outcome)
 }
 
-fn use_rayon<I, P: Printable>(iterator: I, re: &Regex, print_behaviour: P) -> Result<(), MyErrors>
-where
-    I: ParallelIterator<Item = Result<FileInfo, MyErrors>>,
-{
-    let re = Arc::new(re.to_owned());
-    let results: Vec<_> = iterator
-        .filter_map(|item| match item {
-            Ok(file) => Some(file),
-            Err(err) => {
-                eprintln!("Error parsing item: {}", err);
-                None
-            }
-        })
-        .filter_map(|file| {
-            let re: Arc<Regex> = Arc::clone(&re);
-            match find_entry_within_file_rayon(&file, &re) {
-                Err(err) => {
-                    eprintln!("Error while searching file {}", err);
-                    None
-                }
-                Ok(found) => {
-                    if !found.is_empty() {
-                        Some((file.get_identifier(), found))
-                    } else {
-                        None
-                    }
-                }
-            }
-        })
-        .collect();
+/// Runs on its own pool of `tuning.threads` rather than rayon's global pool.
+pub(crate) fn use_rayon<P: Printable>(
+    search: Search,
+    print_behaviour: P,
+) -> Result<Outcome, MyErrors> {
+    let Search {
+        walk,
+        matcher,
+        output,
+        stats,
+        sort,
+        mmap,
+        tuning,
+        cancel,
+    } = search;
+    let iterator = walk.par_files(&stats, &cancel);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(tuning.threads)
+        .build()
+        .map_err(|err| MyErrors::ThreadPanic(err.to_string()))?;
+    // Large files are split across the same pool
+    let pool = Arc::new(pool);
+    let chunker = Chunker::with_pool(Arc::clone(&pool));
+
+    let (tx, rx) = crossbeam_channel::bounded(tuning.channel_capacity);
+    let print_handle = spawn_printer(
+        rx,
+        print_behaviour,
+        output,
+        Arc::clone(&stats),
+        sort,
+        cancel.clone(),
+    );
+
+    pool.install(|| {
+        iterator.for_each_with(tx, |tx, (seq, file)| {
+            // Rayon's workers are scoped to this call, so the matcher is shared by reference.
+            // Files already bridged from the walk when cancelled are skipped
+            let found = search_file(&file, &matcher, &chunker, &stats, mmap, &cancel);
+            send_found(tx, &stats, sort, seq, &file.path, found);
+        });
+    });
 
-    print_behaviour.writeln(results, |r| information_out(&r))?;
+    print_handle
+        .join()
+        .map_err(|err| MyErrors::ThreadPanic(format!("{:?}", err)))?
+}
 
-    Ok(())
+/// The walk for the strategies which print, an error is reported as the walk reaches it.
+fn find_files(
+    inputs: Vec<Input>,
+    re: Option<Matcher>,
+    search_archives: bool,
+    sort: Sort,
+    stats: Arc<Stats>,
+    cancel: CancellationToken,
+) -> impl Iterator<Item = FileInfo> + Send {
+    let error_stats = Arc::clone(&stats);
+    walk_files(inputs, re, search_archives, sort, stats, cancel).filter_map(move |file| {
+        // Walk errors carry their own path
+        file.map_err(|err| error_stats.error(Path::new(""), &err))
+            .ok()
+    })
 }
 
-fn find_files(dir: &Path, re: Option<Regex>) -> impl Iterator<Item = FileInfo> {
-    let iterator = WalkDir::new(dir).into_iter().filter_map(move |entry| {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(err) => {
-                eprintln!("File/Dir error: {}", err);
-                // Some(Err(MyErrors::WalkDir(err)));
-                return None;
+/// Every file to search, with an error in place of each path or archive member which couldn't be walked.
+fn walk_files(
+    inputs: Vec<Input>,
+    re: Option<Matcher>,
+    search_archives: bool,
+    sort: Sort,
+    stats: Arc<Stats>,
+    cancel: CancellationToken,
+) -> impl Iterator<Item = Result<FileInfo, MyErrors>> + Send {
+    let walk_stats = Arc::clone(&stats);
+    TimedWalk::new(
+        walk_inputs(inputs, sort, cancel).flat_map(move |input| match input {
+            Ok(input) => {
+                rayon::iter::Either::Left(expand_input(input, &re, search_archives, &walk_stats))
             }
-        };
-
-        if !entry.file_type().is_file() {
-            return None;
-        }
+            Err(err) => rayon::iter::Either::Right(std::iter::once(Err(err))),
+        }),
+        stats,
+    )
+}
 
-        let path = entry.path();
-        let filename = path.file_name().and_then(|os_str| os_str.to_str());
+/// Archives are expanded before bridging so every file searched has a sequence number in walk order.
+fn rayon_find_files(
+    inputs: Vec<Input>,
+    re: Option<Matcher>,
+    search_archives: bool,
+    sort: Sort,
+    stats: Arc<Stats>,
+    cancel: CancellationToken,
+) -> impl ParallelIterator<Item = (usize, FileInfo)> {
+    find_files(inputs, re, search_archives, sort, stats, cancel)
+        .enumerate()
+        .par_bridge()
+}
 
-        match filename {
-            Some(filename) => {
-                if let Some(re) = &re {
-                    if re.is_match(filename) {
-                        Some(FileInfo {
-                            path: path.to_path_buf(),
-                            filename: filename.to_string(),
-                        })
-                    } else {
-                        None
-                    }
-                } else {
-                    Some(FileInfo {
-                        path: path.to_path_buf(),
-                        filename: filename.to_string(),
-                    })
-                }
+/**
+ * The walk as a stream, for the async strategy.
+ *
+ * Walking is blocking IO, so the same walk as `find_files` runs on one of tokio's blocking threads
+ * and sends each file over a channel of `capacity`. Searching starts with the first file found
+ * rather than once the walk is done, and the runtime's threads are never blocked by it.
+ * Errors walking are yielded in place of the file, a walk which panicked ends the stream with one.
+ */
+fn async_find_files(
+    inputs: Vec<Input>,
+    re: Option<Matcher>,
+    search_archives: bool,
+    sort: Sort,
+    stats: Arc<Stats>,
+    cancel: CancellationToken,
+    capacity: usize,
+) -> impl Stream<Item = Result<FileInfo, MyErrors>> {
+    let (tx, rx) = tokio::sync::mpsc::channel(capacity.max(1));
+    let walker = tokio::task::spawn_blocking(move || {
+        for file in walk_files(inputs, re, search_archives, sort, stats, cancel) {
+            // Nothing is listening any more, e.g. the output couldn't be written
+            if tx.blocking_send(file).is_err() {
+                break;
             }
-            None => None,
         }
     });
-    iterator
+
+    stream::unfold((rx, Some(walker)), |(mut rx, walker)| async move {
+        match rx.recv().await {
+            Some(file) => Some((file, (rx, walker))),
+            None => match walker?.await {
+                Ok(()) => None,
+                Err(err) => Some((Err(MyErrors::TokioError(err)), (rx, None))),
+            },
+        }
+    })
 }
 
-fn rayon_find_files(
-    dir: &Path,
-    re: Option<Regex>,
-) -> impl ParallelIterator<Item = Result<FileInfo, MyErrors>> {
-    let iterator = WalkDir::new(dir)
+/// Walks every path input, producing an `Input::Path` per file found (or an error for a path which
+/// couldn't be walked). Stdin is passed straight through.
+/// Cancelling stops the walk, including while collecting paths to sort by metadata.
+fn walk_inputs(
+    mut inputs: Vec<Input>,
+    sort: Sort,
+    cancel: CancellationToken,
+) -> Box<dyn Iterator<Item = Result<Input, MyErrors>> + Send> {
+    sort.sort_inputs(&mut inputs);
+
+    let walk_cancel = cancel.clone();
+    let walked = inputs
         .into_iter()
-        .par_bridge()
-        .filter_map(|entry| match entry {
-            Ok(entry) if entry.file_type().is_file() => Some(entry),
-            Ok(_) => None,
-            Err(err) => {
-                eprintln!("Error reading entry: {}", err);
-                None
-            }
-        })
-        .filter_map(move |entry| {
-            let path = entry.path();
-            let filename = path.file_name().and_then(|os_str| os_str.to_str());
-            match filename {
-                Some(filename) => {
-                    if let Some(re) = &re {
-                        if re.is_match(filename) {
-                            Some(Ok(FileInfo {
-                                path: path.to_path_buf(),
-                                filename: filename.to_string(),
-                            }))
-                        } else {
-                            None
-                        }
-                    } else {
-                        Some(Ok(FileInfo {
-                            path: path.to_path_buf(),
-                            filename: filename.to_string(),
-                        }))
-                    }
+        .flat_map(
+            move |input| -> Box<dyn Iterator<Item = Result<Input, MyErrors>> + Send> {
+                match input {
+                    Input::Stdin(_) => Box::new(std::iter::once(Ok(input))),
+                    Input::Path(path) => Box::new(sort.walker(&path).into_iter().filter_map(
+                        move |entry| match entry {
+                            Ok(entry) if entry.file_type().is_file() => {
+                                Some(Ok(Input::Path(entry.into_path())))
+                            }
+                            Ok(_) => None,
+                            Err(err) => Some(Err(MyErrors::WalkDir {
+                                path: err.path().unwrap_or(&path).to_path_buf(),
+                                source: err,
+                            })),
+                        },
+                    )),
                 }
-                None => None,
-            }
-        });
+            },
+        )
+        .take_while(move |_| !walk_cancel.is_cancelled());
+
+    if sort.needs_metadata() {
+        // Errors have nothing to sort by, so come before every file
+        let (inputs, errors): (Vec<_>, Vec<_>) = walked.partition(Result::is_ok);
+        let sorted = sort.sort_by_metadata(inputs.into_iter().flatten().collect());
+        return Box::new(
+            errors
+                .into_iter()
+                .chain(sorted.into_iter().map(Ok))
+                .take_while(move |_| !cancel.is_cancelled()),
+        );
+    }
+    Box::new(walked)
+}
 
-    iterator
+fn expand_input(
+    input: Input,
+    re: &Option<Matcher>,
+    search_archives: bool,
+    stats: &Arc<Stats>,
+) -> impl Iterator<Item = Result<FileInfo, MyErrors>> + Send + use<> {
+    match input {
+        // Nothing to filter on as there is no filename
+        Input::Stdin(contents) => rayon::iter::Either::Left(std::iter::once(Ok(FileInfo {
+            path: PathBuf::from(input::STDIN_NAME),
+            filename: input::STDIN_NAME.to_string(),
+            contents: Some(contents),
+        }))),
+        Input::Path(path) => {
+            rayon::iter::Either::Right(expand_entry(&path, re, search_archives, stats))
+        }
+    }
+}
+
+fn matches_path_pattern(re: &Option<Matcher>, filename: &str) -> bool {
+    re.as_ref().is_none_or(|re| re.is_match(filename))
+}
+
+/**
+ * Turns a walked file into the files to be searched.
+ *
+ * Plain files yield at most one `FileInfo`, archives (when enabled) yield one per member that
+ * passes the path pattern, decompressed as the walk reaches it. Only archives box their members,
+ * so the common case doesn't allocate.
+ */
+fn expand_entry(
+    path: &Path,
+    re: &Option<Matcher>,
+    search_archives: bool,
+    stats: &Arc<Stats>,
+) -> impl Iterator<Item = Result<FileInfo, MyErrors>> + Send + use<> {
+    if search_archives && let Some(kind) = ArchiveKind::from_path(path) {
+        return rayon::iter::Either::Right(expand_archive(path, kind, re, stats));
+    }
+
+    let file = path
+        .file_name()
+        .and_then(|os_str| os_str.to_str())
+        .filter(|filename| matches_path_pattern(re, filename))
+        .map(|filename| FileInfo::new(path, filename));
+    stats.walked(file.is_none());
+
+    rayon::iter::Either::Left(file.into_iter().map(Ok))
+}
+
+/// A member which fails to read is an error against `archive!/member`, the rest are still searched.
+fn expand_archive(
+    path: &Path,
+    kind: ArchiveKind,
+    re: &Option<Matcher>,
+    stats: &Arc<Stats>,
+) -> impl Iterator<Item = Result<FileInfo, MyErrors>> + Send + use<> {
+    let keep_re = re.clone();
+    let keep_stats = Arc::clone(stats);
+    let keep = move |filename: &str| {
+        let keep = matches_path_pattern(&keep_re, filename);
+        keep_stats.walked(!keep);
+        keep
+    };
+
+    let archive = path.to_path_buf();
+    archive::read_members(path, kind, keep).map(move |member| {
+        member.map(|member| FileInfo {
+            path: archive::member_path(&archive, &member.name),
+            filename: member.filename().to_string(),
+            contents: Some(member.contents),
+        })
+    })
 }
 
 /**
@@ -825,142 +1658,234 @@ fn rayon_find_files(
  * TODO: either expand on this OR more likely make separate ones (in particular for Rayon)
  */
 #[allow(dead_code)]
-fn find_entry_within_file(f: &FileInfo, re: &Regex) -> Result<Vec<String>, MyErrors> {
-    let file = File::open(&f.path).map_err(MyErrors::FileIO)?;
-    let reader = BufReader::new(file);
+fn find_entry_within_file(f: &FileInfo, matcher: &Matcher) -> Result<Vec<LineMatch>, MyErrors> {
+    let file = File::open(&f.path).map_err(|e| MyErrors::io(&f.path, Operation::Open, e))?;
+    let mut reader = BufReader::new(file);
 
     let mut found_lines = Vec::new();
-    for (idx, line) in reader.lines().enumerate() {
-        let line = line.map_err(MyErrors::FileIO)?;
-
-        let replaced = re.replace_all(&line, |caps: &regex::Captures| {
-            Colour::Red.paint(&caps[0]).to_string()
-        });
+    let mut line = String::new();
+    let mut byte_offset = 0;
+    let mut line_number = 1;
+    loop {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| MyErrors::io(&f.path, Operation::Read, e))?;
+        if read == 0 {
+            break;
+        }
 
-        if let Cow::Owned(_) = replaced {
-            found_lines.push(format!(
-                "{}:{}",
-                Colour::Green.paint(format!("{}", idx + 1)),
-                replaced
-            ));
+        let trimmed = line.trim_end_matches('\n').trim_end_matches('\r');
+        let terminator = LineTerminator::at(&line.as_bytes()[trimmed.len()..]);
+        if let Some(found) = find_in_str_line(
+            matcher.regex(),
+            trimmed,
+            terminator,
+            line_number,
+            byte_offset,
+        ) {
+            found_lines.push(found);
         }
+
+        byte_offset += read;
+        line_number += 1;
     }
 
     Ok(found_lines)
 }
 
-fn find_entry_with_file_memmap(f: &FileInfo, re: &Regex) -> Result<Vec<String>, MyErrors> {
-    let mut found_lines = Vec::new();
-    let byte_re = bytes::Regex::new(re.as_str()).map_err(MyErrors::Regex)?;
+/// The file's matching lines, which are empty when nothing matched.
+fn find_entry_with_file_memmap(
+    f: &FileInfo,
+    matcher: &Matcher,
+    chunker: &Chunker,
+    stats: &Stats,
+    mmap: MmapChoice,
+) -> Result<FileMatches, MyErrors> {
+    let start = Instant::now();
+    let search = |contents: &[u8]| {
+        stats.searched(contents.len());
+        let found = find_entry_within_bytes_chunked(contents, matcher, chunker);
+        FileMatches::new(f, found, contents.len(), start.elapsed())
+    };
 
-    let file = File::open(&f.path).map_err(MyErrors::FileIO)?;
+    if let Some(contents) = &f.contents {
+        return Ok(search(contents));
+    }
 
-    // TODO: test .map vs .map_copy
-    let mmap = unsafe { MmapOptions::new().map(&file).map_err(MyErrors::FileIO)? };
+    // Falls back to a buffered read for files that could change while mapped, see `MmapChoice`
+    let contents = mmap.read(&f.path)?;
+    Ok(search(&contents))
+}
 
-    let mut pos = 0;
-    let mut line_num = 1;
+/// Below this a file is searched on the calling thread, splitting it would cost more than it saves.
+const MIN_CHUNK_LEN: usize = 4 * 1024 * 1024;
 
-    while pos < mmap.len() {
-        let end = mmap[pos..]
-            .iter()
-            .position(|&b| b == b'\n')
-            .map(|p| pos + p)
-            .unwrap_or(mmap.len());
-
-        // Check for CRLF
-        let is_crlf = end > pos && mmap[end - 1] == b'\r';
-        let line_bytes = if is_crlf {
-            &mmap[pos..end - 1] // Exclude \r
-        } else {
-            &mmap[pos..end]
-        };
+/**
+ * Where a large file's chunks are searched: a rayon pool of at most `threads`.
+ *
+ * The pool is only built once a file is large enough to be split, and the rayon strategy hands over
+ * its own. With a single thread nothing is split, so the single threaded strategies stay that way.
+ */
+struct Chunker {
+    threads: usize,
+    pool: OnceLock<Option<Arc<rayon::ThreadPool>>>,
+}
 
-        // let line_bytes = &mmap[pos..end];
-        let line_str = String::from_utf8_lossy(line_bytes);
-        let mut current_pos = 0;
+impl Chunker {
+    fn new(threads: usize) -> Self {
+        Chunker {
+            threads,
+            pool: OnceLock::new(),
+        }
+    }
 
-        let mut modified_line: Option<String> = None;
+    fn with_pool(pool: Arc<rayon::ThreadPool>) -> Self {
+        Chunker {
+            threads: pool.current_num_threads(),
+            pool: OnceLock::from(Some(pool)),
+        }
+    }
 
-        for cap in byte_re.captures_iter(line_bytes) {
-            if let Some(m) = cap.get(0) {
-                let range = m.range();
-                if range.start == range.end {
-                    break;
-                }
+    /// `None` if the pool couldn't be built, large files are then searched on the calling thread.
+    fn pool(&self) -> Option<&rayon::ThreadPool> {
+        self.pool
+            .get_or_init(|| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(self.threads)
+                    .build()
+                    .ok()
+                    .map(Arc::new)
+            })
+            .as_deref()
+    }
+}
 
-                let mat = modified_line.get_or_insert_with(|| line_str[..current_pos].to_string());
-                mat.push_str(&line_str[current_pos..range.start]);
-                mat.push_str(&format!(
-                    "{}",
-                    Colour::Red.paint(&line_str[range.start..range.end])
-                ));
+/**
+ * Searches a large file in line aligned chunks on the chunker's pool, so a single multi GB log
+ * isn't left to one thread.
+ *
+ * Each chunk is searched as if it were its own file, then line numbers and offsets are shifted
+ * by the lines and bytes of the chunks before it.
+ */
+fn find_entry_within_bytes_chunked(
+    contents: &[u8],
+    matcher: &Matcher,
+    chunker: &Chunker,
+) -> Vec<LineMatch> {
+    let chunks = line_aligned_chunks(contents, chunker.threads, MIN_CHUNK_LEN);
+    if chunks.len() <= 1 {
+        return matcher.find_lines(contents);
+    }
 
-                current_pos = range.end;
-            }
-        }
+    match chunker.pool() {
+        Some(pool) => pool.install(|| search_in_chunks(contents, matcher, chunks)),
+        None => matcher.find_lines(contents),
+    }
+}
 
-        if let Some(mut m1) = modified_line {
-            m1.push_str(&line_str[current_pos..]);
-            found_lines.push(format!(
-                "{}:{}",
-                Colour::Green.paint(format!("{}", line_num)),
-                m1
-            ));
-        }
+fn search_in_chunks(
+    contents: &[u8],
+    matcher: &Matcher,
+    chunks: Vec<Range<usize>>,
+) -> Vec<LineMatch> {
+    let searched: Vec<(Range<usize>, usize, Vec<LineMatch>)> = chunks
+        .into_par_iter()
+        .map(|chunk| {
+            let chunk_contents = &contents[chunk.clone()];
+            let found = matcher.find_lines(chunk_contents);
+            (chunk, lines::count_lines(chunk_contents), found)
+        })
+        .collect();
 
-        pos = if end < mmap.len() { end + 1 } else { end };
-        line_num += 1;
+    let mut found_lines = Vec::new();
+    let mut lines_before = 0;
+    for (chunk, lines, found) in searched {
+        found_lines.extend(found.into_iter().map(|mut line| {
+            line.line_number += lines_before;
+            line.byte_offset += chunk.start;
+            line
+        }));
+        lines_before += lines;
     }
 
-    Ok(found_lines)
+    found_lines
 }
 
-fn find_entry_within_file_rayon(f: &FileInfo, re: &Regex) -> Result<Vec<String>, MyErrors> {
-    let file = File::open(&f.path).map_err(MyErrors::FileIO)?;
-    let reader = BufReader::with_capacity(8 * 1024, file);
+/// Splits `contents` into at most `count` ranges of at least `min_len`, each ending just after a newline (bar the last).
+fn line_aligned_chunks(contents: &[u8], count: usize, min_len: usize) -> Vec<Range<usize>> {
+    let chunk_len = (contents.len() / count.max(1)).max(min_len);
 
-    let mut results: Vec<(usize, String)> = reader
-        .lines()
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(MyErrors::FileIO)?
-        .into_par_iter()
-        .enumerate()
-        .filter_map(|(idx, line)| {
-            let replaced = re.replace_all(&line, |caps: &regex::Captures| {
-                let matched = &caps[0];
-                let coloured = Colour::Red.paint(matched);
-                coloured.to_string()
-            });
-
-            // equivalent to:
-            if let Cow::Owned(_) = replaced {
-                Some((
-                    idx,
-                    format!(
-                        "{}:{}",
-                        Colour::Green.paint(format!("{}", idx + 1)),
-                        replaced
-                    ),
-                ))
-            } else {
-                None
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < contents.len() {
+        let end = match contents.get(start + chunk_len..) {
+            Some(rest) => {
+                memchr::memchr(b'\n', rest).map_or(contents.len(), |p| start + chunk_len + p + 1)
             }
-        })
-        .collect();
+            None => contents.len(),
+        };
+        chunks.push(Range { start, end });
+        start = end;
+    }
 
-    results.par_sort_unstable_by_key(|(idx, _)| *idx);
-    Ok(results.into_iter().map(|(_, f)| f).collect())
+    chunks
+}
+
+/// Used by the strategies which decode the whole file as UTF-8 before searching it.
+fn find_in_str_line(
+    re: &Regex,
+    line: &str,
+    terminator: LineTerminator,
+    line_number: usize,
+    byte_offset: usize,
+) -> Option<LineMatch> {
+    let submatches: Vec<Range<usize>> = re.find_iter(line).map(|m| m.range()).collect();
+    if submatches.is_empty() {
+        return None;
+    }
+
+    Some(LineMatch {
+        line_number,
+        byte_offset,
+        line: line.as_bytes().to_vec(),
+        terminator,
+        submatches,
+    })
+}
+
+/// Offset of `line` within `contents`, `line` must have been sliced from `contents`.
+fn offset_within(contents: &str, line: &str) -> usize {
+    line.as_ptr() as usize - contents.as_ptr() as usize
 }
 
 #[cfg(test)]
 mod tests {
+    use std::ops::Range;
     use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::time::Duration;
 
     use crate::{
-        FileInfo, find_entry_with_file_memmap,
-        my_regex::{self, SearchMode},
+        Chunker, Cli, FileInfo, FileMatches, LineMatch, MIN_CHUNK_LEN, MyErrors, Operation,
+        PrintDisable, PrintEnabled, Search, StreamPrinter,
+        cancel::CancellationToken,
+        colour::ColourSpecs,
+        find_entry_with_file_memmap, find_entry_within_bytes_chunked, find_files, finished_search,
+        input::{self, Input},
+        line_aligned_chunks,
+        lines::{self, LineTerminator},
+        mmap::MmapChoice,
+        my_regex::{self, Matcher, SearchMode},
+        output::{self, Output, OutputFormat},
+        search_stream,
+        sort::{Sort, SortBy},
+        stats::Stats,
+        use_async_two,
     };
+    use clap::Parser;
+    use futures::StreamExt;
+    use proptest::prelude::*;
 
     #[test]
     fn test_find_entry_with_file_memmap_basic_regex() {
@@ -969,6 +1894,7 @@ mod tests {
         let file_info: FileInfo = FileInfo {
             filename: filename.to_string(),
             path: file_path,
+            contents: None,
         };
 
         let re = my_regex::clean_up_regex(
@@ -978,7 +1904,13 @@ mod tests {
         .expect("Expected to be able to create regex from string")
         .unwrap();
 
-        let r = find_entry_with_file_memmap(&file_info, &re);
+        let r = find_entry_with_file_memmap(
+            &file_info,
+            &re,
+            &Chunker::new(1),
+            &Stats::default(),
+            MmapChoice::default(),
+        );
 
         let expected_results: [String; 1] = [format!(
             "{}:{}{}",
@@ -988,7 +1920,13 @@ mod tests {
         )];
         let x = expected_results.to_vec();
 
-        assert_eq!(r.ok().unwrap(), x)
+        let rendered: Vec<String> = r
+            .unwrap()
+            .lines
+            .iter()
+            .map(|l| output::render_line(l, &ColourSpecs::default()))
+            .collect();
+        assert_eq!(rendered, x)
     }
 
     #[test]
@@ -998,6 +1936,7 @@ mod tests {
         let file_info: FileInfo = FileInfo {
             filename: filename.to_string(),
             path: file_path,
+            contents: None,
         };
 
         let re = my_regex::clean_up_regex(
@@ -1007,7 +1946,13 @@ mod tests {
         .expect("Expected to be able to create regex from string")
         .unwrap();
 
-        let r = find_entry_with_file_memmap(&file_info, &re);
+        let r = find_entry_with_file_memmap(
+            &file_info,
+            &re,
+            &Chunker::new(1),
+            &Stats::default(),
+            MmapChoice::default(),
+        );
 
         let expected_results: [String; 1] = [format!(
             "{}:{}{}",
@@ -1017,6 +1962,488 @@ mod tests {
         )];
         let x = expected_results.to_vec();
 
-        assert_eq!(r.ok().unwrap(), x)
+        let rendered: Vec<String> = r
+            .unwrap()
+            .lines
+            .iter()
+            .map(|l| output::render_line(l, &ColourSpecs::default()))
+            .collect();
+        assert_eq!(rendered, x)
+    }
+
+    #[test]
+    fn test_find_files_expands_archive_members() {
+        use std::io::Write;
+
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        let archive_path = temp_dir.path().join("bundle.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("docs/inside.txt", options).unwrap();
+        writer.write_all(b"first line\nneedle here\n").unwrap();
+        writer.start_file("docs/inside.md", options).unwrap();
+        writer.write_all(b"needle here too\n").unwrap();
+        writer.finish().unwrap();
+
+        let path_pattern = my_regex::clean_up_regex(Some(".txt"), SearchMode::Literal).unwrap();
+        let files: Vec<FileInfo> = find_files(
+            vec![Input::Path(temp_dir.path().to_path_buf())],
+            path_pattern,
+            true,
+            Sort::default(),
+            Arc::new(Stats::default()),
+            CancellationToken::new(),
+        )
+        .collect();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].path,
+            PathBuf::from(format!("{}!/docs/inside.txt", archive_path.display()))
+        );
+        assert_eq!(files[0].filename, "inside.txt");
+
+        let re = my_regex::clean_up_regex(Some("needle"), SearchMode::Literal)
+            .unwrap()
+            .unwrap();
+        let r = find_entry_with_file_memmap(
+            &files[0],
+            &re,
+            &Chunker::new(1),
+            &Stats::default(),
+            MmapChoice::default(),
+        )
+        .unwrap();
+        let rendered: Vec<String> = r
+            .lines
+            .iter()
+            .map(|l| output::render_line(l, &ColourSpecs::default()))
+            .collect();
+        assert_eq!(
+            rendered,
+            vec![format!(
+                "{}:{} here",
+                ansi_term::Color::Green.paint("2"),
+                ansi_term::Color::Red.paint("needle")
+            )]
+        );
+
+        let without_archives = find_files(
+            vec![Input::Path(temp_dir.path().to_path_buf())],
+            None,
+            false,
+            Sort::default(),
+            Arc::new(Stats::default()),
+            CancellationToken::new(),
+        )
+        .count();
+        assert_eq!(without_archives, 1, "The archive itself is a single file");
+
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_find_files_stdin_input() {
+        let inputs = vec![Input::Stdin(b"nothing\r\nfind the needle\r\n".to_vec())];
+        // The path pattern doesn't apply as stdin has no filename
+        let path_pattern = my_regex::clean_up_regex(Some(".txt"), SearchMode::Literal).unwrap();
+        let files: Vec<FileInfo> = find_files(
+            inputs,
+            path_pattern,
+            false,
+            Sort::default(),
+            Arc::new(Stats::default()),
+            CancellationToken::new(),
+        )
+        .collect();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].filename, input::STDIN_NAME);
+
+        let re = my_regex::clean_up_regex(Some("needle"), SearchMode::Literal)
+            .unwrap()
+            .unwrap();
+        let r = find_entry_with_file_memmap(
+            &files[0],
+            &re,
+            &Chunker::new(1),
+            &Stats::default(),
+            MmapChoice::default(),
+        )
+        .unwrap();
+        let rendered: Vec<String> = r
+            .lines
+            .iter()
+            .map(|l| output::render_line(l, &ColourSpecs::default()))
+            .collect();
+        assert_eq!(
+            rendered,
+            vec![format!(
+                "{}:find the {}",
+                ansi_term::Color::Green.paint("2"),
+                ansi_term::Color::Red.paint("needle")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_find_files_sorted_by_path() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        for name in ["b.txt", "a/c.txt", "c.txt", "a/a.txt"] {
+            let path = temp_dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "needle").unwrap();
+        }
+
+        let walk = |reverse| -> Vec<PathBuf> {
+            let sort = Sort {
+                by: SortBy::Path,
+                reverse,
+            };
+            find_files(
+                vec![Input::Path(temp_dir.path().to_path_buf())],
+                None,
+                false,
+                sort,
+                Arc::new(Stats::default()),
+                CancellationToken::new(),
+            )
+            .map(|f| f.path.strip_prefix(temp_dir.path()).unwrap().to_path_buf())
+            .collect()
+        };
+
+        let expected: Vec<PathBuf> = ["a/a.txt", "a/c.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(walk(false), expected);
+        assert_eq!(walk(true), expected.into_iter().rev().collect::<Vec<_>>());
+
+        temp_dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_cancelled_run_stops_walking() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let files = find_files(
+            vec![Input::Path(PathBuf::from("test_files"))],
+            None,
+            false,
+            Sort::default(),
+            Arc::new(Stats::default()),
+            cancel.clone(),
+        )
+        .count();
+        assert_eq!(files, 0);
+
+        let output = Output::new(OutputFormat::Vimgrep, ColourSpecs::plain());
+        let mut buffer = Vec::new();
+        let printer = StreamPrinter::new(
+            &mut buffer,
+            PrintEnabled,
+            output,
+            Arc::new(Stats::default()),
+            Sort::default(),
+            cancel,
+        )
+        .unwrap();
+        let outcome = printer.finish().unwrap();
+        assert!(outcome.cancelled);
+        assert_eq!(outcome.exit_code(), 130);
+    }
+
+    #[tokio::test]
+    async fn test_async_walk_searches_non_utf8() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("latin1.txt"), b"caf\xe9 needle\n").unwrap();
+        std::fs::write(temp_dir.path().join("utf8.txt"), "needle\n").unwrap();
+
+        let cli = Cli::parse_from(["rustcomb", temp_dir.path().to_str().unwrap(), "needle"]);
+        let search = Search::from_cli(&cli, CancellationToken::new()).unwrap();
+        let stats = Arc::clone(&search.stats);
+        let outcome = use_async_two(search, PrintDisable).await.unwrap();
+
+        assert!(outcome.matched);
+        assert_eq!(outcome.errors, 0, "Invalid UTF-8 is searched, not an error");
+        assert_eq!(stats.report(&Default::default()).files_searched, 2);
+
+        temp_dir.close().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_search_stream() {
+        let temp_dir = assert_fs::TempDir::new().unwrap();
+        for name in ["b.txt", "a.txt", "c.txt", "none.txt"] {
+            let contents = if name == "none.txt" { "hay" } else { "needle" };
+            std::fs::write(temp_dir.path().join(name), contents).unwrap();
+        }
+        let dir = temp_dir.path().to_str().unwrap();
+
+        let cli = Cli::parse_from(["rustcomb", dir, "needle", "--sort", "path", "-j", "2"]);
+        let found: Vec<String> = search_stream(Arc::new(cli))
+            .await
+            .map(|found| found.unwrap().filename)
+            .collect()
+            .await;
+        assert_eq!(found, vec!["a.txt", "b.txt", "c.txt"]);
+
+        let cli = Cli::parse_from(["rustcomb", dir, "(", "--file-pattern-regex", "regex"]);
+        let errors: Vec<_> = search_stream(Arc::new(cli)).await.collect().await;
+        assert!(matches!(errors[..], [Err(MyErrors::Regex(_))]));
+
+        // Walk and archive errors are yielded too, alongside the files which could be searched
+        let broken = temp_dir.path().join("broken.zip");
+        std::fs::write(&broken, "not a zip").unwrap();
+        let cli = Cli::parse_from(["rustcomb", dir, "needle", "-z", "--sort", "path"]);
+        let searched: Vec<_> = search_stream(Arc::new(cli)).await.collect().await;
+        let (found, errors): (Vec<_>, Vec<_>) = searched.into_iter().partition(Result::is_ok);
+        assert_eq!(found.len(), 3);
+        let err = errors[0].as_ref().unwrap_err();
+        assert_eq!(
+            (errors.len(), err.code(), err.path()),
+            (1, "invalid_archive", Some(broken.as_path()))
+        );
+
+        let missing = temp_dir.path().join("missing");
+        let cli = Cli::parse_from(["rustcomb", missing.to_str().unwrap(), "needle"]);
+        let errors: Vec<_> = search_stream(Arc::new(cli)).await.collect().await;
+        assert!(matches!(&errors[..], [Err(MyErrors::WalkDir { path, .. })] if *path == missing));
+
+        temp_dir.close().unwrap();
+    }
+
+    /// A file whose only line is `needle`.
+    fn found(name: &str) -> FileMatches {
+        FileMatches {
+            path: PathBuf::from(name),
+            filename: name.to_string(),
+            lines: vec![LineMatch {
+                line_number: 1,
+                byte_offset: 0,
+                line: b"needle".to_vec(),
+                terminator: LineTerminator::Lf,
+                submatches: vec![Range { start: 0, end: 6 }],
+            }],
+            bytes_searched: 7,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_stream_printer_reorders() {
+        let output = Output::new(OutputFormat::Vimgrep, ColourSpecs::plain());
+        let sort = Sort {
+            by: SortBy::Path,
+            reverse: false,
+        };
+
+        let mut buffer = Vec::new();
+        let mut printer = StreamPrinter::new(
+            &mut buffer,
+            PrintEnabled,
+            output,
+            Arc::new(Stats::default()),
+            sort,
+            CancellationToken::new(),
+        )
+        .unwrap();
+        printer.push(2, Some(found("c"))).unwrap();
+        printer.push(0, Some(found("a"))).unwrap();
+        printer.push(1, None).unwrap();
+        printer.push(3, Some(found("d"))).unwrap();
+        printer.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "a:1:1:needle\nc:1:1:needle\nd:1:1:needle\n"
+        );
+    }
+
+    #[test]
+    fn test_stream_printer_writes_results_held_at_finish() {
+        let stats = Arc::new(Stats::new(true));
+        let mut buffer = Vec::new();
+        let mut printer = StreamPrinter::new(
+            &mut buffer,
+            PrintEnabled,
+            Output::new(OutputFormat::Vimgrep, ColourSpecs::plain()),
+            Arc::clone(&stats),
+            Sort {
+                by: SortBy::Path,
+                reverse: false,
+            },
+            CancellationToken::new(),
+        )
+        .unwrap();
+        // The result for 0 never arrives, e.g. its worker died
+        printer.push(1, Some(found("b"))).unwrap();
+        printer.push(2, Some(found("c"))).unwrap();
+        let outcome = printer.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "b:1:1:needle\nc:1:1:needle\n"
+        );
+        assert_eq!(outcome.errors, 1);
+        assert!(outcome.matched);
+    }
+
+    #[test]
+    fn test_panicking_search_is_reported() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let stats = Stats::default();
+        let sort = Sort {
+            by: SortBy::Path,
+            reverse: false,
+        };
+
+        let handle = std::thread::spawn(|| panic!("search failed"));
+        finished_search(&tx, sort, &stats, (3, PathBuf::from("panics.txt"), handle));
+
+        assert_eq!(stats.error_count(), 1);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            (3, None),
+            "Its place is still filled"
+        );
+
+        let handle = std::thread::spawn(|| ());
+        finished_search(&tx, sort, &stats, (4, PathBuf::from("fine.txt"), handle));
+        assert_eq!(stats.error_count(), 1);
+        assert!(rx.try_recv().is_err(), "The thread sends its own result");
+
+        // The printer has stopped, so the gap can't be filled either
+        drop(rx);
+        let handle = std::thread::spawn(|| panic!("search failed"));
+        finished_search(&tx, sort, &stats, (5, PathBuf::from("lost.txt"), handle));
+        assert_eq!(stats.error_count(), 3);
+    }
+
+    #[test]
+    fn test_missing_file_error_has_path_and_operation() {
+        let file_info = FileInfo {
+            filename: "missing.txt".to_string(),
+            path: Path::new("test_files").join("missing.txt"),
+            contents: None,
+        };
+        let re = my_regex::clean_up_regex(Some("needle"), SearchMode::Literal)
+            .unwrap()
+            .unwrap();
+
+        let err = find_entry_with_file_memmap(
+            &file_info,
+            &re,
+            &Chunker::new(1),
+            &Stats::default(),
+            MmapChoice::default(),
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "io_open");
+        assert_eq!(err.operation(), Some(Operation::Open));
+        assert_eq!(err.path(), Some(file_info.path.as_path()));
+        assert!(
+            err.to_string()
+                .starts_with("File IO error, failed to open test_files/missing.txt: ")
+        );
+
+        assert_eq!(MyErrors::MissingPattern.code(), "missing_pattern");
+        assert_eq!(MyErrors::MissingPattern.path(), None);
+    }
+
+    #[test]
+    fn test_chunked_search_matches_sequential() {
+        let mut contents = Vec::new();
+        for idx in 0..200 {
+            match idx % 3 {
+                0 => contents.extend_from_slice(format!("needle {idx}\r\n").as_bytes()),
+                1 => contents.extend_from_slice(b"hay\n"),
+                _ => contents.extend_from_slice(b"\n"),
+            }
+        }
+        contents.extend_from_slice(b"last needle");
+
+        let chunks = line_aligned_chunks(&contents, 8, 16);
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.first().unwrap().start, 0);
+        assert_eq!(chunks.last().unwrap().end, contents.len());
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert_eq!(contents[pair[0].end - 1], b'\n', "Chunks end on a line");
+        }
+
+        let matcher = Matcher::new("needle").unwrap();
+        let chunked = crate::search_in_chunks(&contents, &matcher, chunks);
+        let sequential = lines::search_each_line(&contents, matcher.bytes());
+        assert_eq!(chunked.len(), 68);
+        assert_eq!(chunked, sequential);
+    }
+
+    #[test]
+    fn test_chunker_only_splits_with_threads() {
+        let mut contents = Vec::new();
+        while contents.len() <= 2 * MIN_CHUNK_LEN {
+            contents.extend_from_slice(b"hay hay hay\nneedle\r\n");
+        }
+        let matcher = Matcher::new("needle").unwrap();
+        let sequential = matcher.find_lines(&contents);
+
+        let single = Chunker::new(1);
+        assert_eq!(
+            find_entry_within_bytes_chunked(&contents, &matcher, &single),
+            sequential
+        );
+        assert!(single.pool.get().is_none(), "No pool for a single thread");
+
+        let chunker = Chunker::new(3);
+        assert_eq!(
+            find_entry_within_bytes_chunked(&contents, &matcher, &chunker),
+            sequential
+        );
+        assert_eq!(chunker.pool().unwrap().current_num_threads(), 3);
+    }
+
+    /// Lines of a few bytes, so chunks of a few lines each, with CRLF and invalid UTF-8.
+    fn contents() -> impl proptest::strategy::Strategy<Value = Vec<u8>> {
+        proptest::string::bytes_regex(r"(?s-u:[ab \n\r\xc3\xa9\xff]{0,300})").unwrap()
+    }
+
+    fn pattern() -> impl proptest::strategy::Strategy<Value = &'static str> {
+        proptest::sample::select(vec!["a", "ab", "(?i)B", "^a", "b$", "a*", "é", r"\w+"])
+    }
+
+    proptest! {
+        #[test]
+        fn prop_chunked_search_matches_sequential(
+            contents in contents(),
+            pattern in pattern(),
+            count in 1..8usize,
+            min_len in 1..32usize,
+        ) {
+            let chunks = line_aligned_chunks(&contents, count, min_len);
+            prop_assert_eq!(chunks.iter().map(|chunk| chunk.len()).sum::<usize>(), contents.len());
+
+            let matcher = Matcher::new(pattern).unwrap();
+            prop_assert_eq!(
+                crate::search_in_chunks(&contents, &matcher, chunks),
+                matcher.find_lines(&contents)
+            );
+        }
+
+        #[test]
+        fn prop_memmap_search_reads_any_bytes(contents in contents(), pattern in pattern()) {
+            let temp_dir = assert_fs::TempDir::new().unwrap();
+            let path = temp_dir.path().join("file.txt");
+            std::fs::write(&path, &contents).unwrap();
+            let file_info = FileInfo::new(&path, "file.txt");
+
+            let matcher = Matcher::new(pattern).unwrap();
+            for mmap in [MmapChoice::Always, MmapChoice::Never] {
+                let found =
+                    find_entry_with_file_memmap(&file_info, &matcher, &Chunker::new(4), &Stats::default(), mmap);
+                prop_assert_eq!(found.unwrap().lines, matcher.find_lines(&contents));
+            }
+        }
     }
 }
@@ -0,0 +1,286 @@
+use std::ops::Range;
+
+use regex::bytes;
+
+use crate::LineMatch;
+use crate::prefilter::Prefilter;
+
+/**
+ * Finds every matching line in `buffer`, searching the whole buffer rather than line by line.
+ *
+ * The prefilter skips straight to the next place a match could start, and only then are the
+ * enclosing line's boundaries found (with `memchr`, searching backwards and forwards). Line numbers
+ * are kept up to date by counting the newlines skipped over. The regex itself is only run on lines
+ * containing a candidate, and on a single line at a time so matches never span lines.
+ */
+pub fn search_lines(buffer: &[u8], re: &bytes::Regex, prefilter: &Prefilter) -> Vec<LineMatch> {
+    let mut found_lines = Vec::new();
+    // Start and number of the next line that hasn't been searched
+    let mut pos = 0;
+    let mut line_number = 1;
+
+    while pos < buffer.len() {
+        let candidate = match prefilter.find(&buffer[pos..]) {
+            Some(offset) => pos + offset,
+            None => break,
+        };
+
+        let start = memchr::memrchr(b'\n', &buffer[pos..candidate]).map_or(pos, |p| pos + p + 1);
+        line_number += count_lines(&buffer[pos..start]);
+        let end =
+            memchr::memchr(b'\n', &buffer[candidate..]).map_or(buffer.len(), |p| candidate + p);
+
+        if let Some(found) = search_line(buffer, Range { start, end }, line_number, re) {
+            found_lines.push(found);
+        }
+
+        pos = if end < buffer.len() { end + 1 } else { end };
+        line_number += 1;
+    }
+
+    found_lines
+}
+
+/// The original loop, running the regex on every line. Kept as the baseline for the benchmarks.
+pub fn search_each_line(buffer: &[u8], re: &bytes::Regex) -> Vec<LineMatch> {
+    let mut found_lines = Vec::new();
+    let mut pos = 0;
+    let mut line_number = 1;
+
+    while pos < buffer.len() {
+        let end = buffer[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map(|p| pos + p)
+            .unwrap_or(buffer.len());
+
+        if let Some(found) = search_line(buffer, Range { start: pos, end }, line_number, re) {
+            found_lines.push(found);
+        }
+
+        pos = if end < buffer.len() { end + 1 } else { end };
+        line_number += 1;
+    }
+
+    found_lines
+}
+
+/// Newlines within `buffer`, i.e. the number of complete lines.
+pub fn count_lines(buffer: &[u8]) -> usize {
+    memchr::memchr_iter(b'\n', buffer).count()
+}
+
+/// What ended a matching line, so it can be written back out exactly as it was.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineTerminator {
+    /// The final line of a file without a trailing newline
+    None,
+    Lf,
+    CrLf,
+    /// A final line ending in a carriage return but no newline
+    Cr,
+}
+
+impl LineTerminator {
+    /// The terminator at the start of `rest`, the bytes following a line.
+    pub fn at(rest: &[u8]) -> Self {
+        if rest.starts_with(b"\r\n") {
+            LineTerminator::CrLf
+        } else if rest.starts_with(b"\n") {
+            LineTerminator::Lf
+        } else if rest.starts_with(b"\r") {
+            LineTerminator::Cr
+        } else {
+            LineTerminator::None
+        }
+    }
+
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineTerminator::None => b"",
+            LineTerminator::Lf => b"\n",
+            LineTerminator::CrLf => b"\r\n",
+            LineTerminator::Cr => b"\r",
+        }
+    }
+}
+
+/// `line` excludes the newline, a trailing `\r` is also excluded for CRLF files.
+fn search_line(
+    buffer: &[u8],
+    line: Range<usize>,
+    line_number: usize,
+    re: &bytes::Regex,
+) -> Option<LineMatch> {
+    let is_crlf = line.end > line.start && buffer[line.end - 1] == b'\r';
+    let text_end = if is_crlf { line.end - 1 } else { line.end };
+    let line_bytes = &buffer[line.start..text_end];
+
+    // An empty match (e.g. `a*` before a `b`) has nothing to highlight, but later matches might
+    let submatches: Vec<Range<usize>> = re
+        .find_iter(line_bytes)
+        .map(|m| m.range())
+        .filter(|range| !range.is_empty())
+        .collect();
+
+    if submatches.is_empty() {
+        return None;
+    }
+
+    Some(LineMatch {
+        line_number,
+        byte_offset: line.start,
+        line: line_bytes.to_vec(),
+        terminator: LineTerminator::at(&buffer[text_end..]),
+        submatches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use regex::bytes;
+
+    use super::{LineTerminator, count_lines, search_each_line, search_lines};
+    use crate::LineMatch;
+    use crate::prefilter::Prefilter;
+
+    /// Small patterns built from pieces which exercise the prefilter, anchors and empty matches.
+    fn pattern() -> impl Strategy<Value = String> {
+        let atom = prop_oneof![
+            "[a-c]{1,3}",
+            Just("é".to_string()),
+            Just(r"\w".to_string()),
+            Just(".".to_string()),
+            Just("a*".to_string()),
+            Just(r"\r".to_string()),
+            Just(r"(?-u:\xff)".to_string()),
+        ];
+        (
+            prop::collection::vec(atom, 1..4),
+            any::<bool>(),
+            any::<bool>(),
+            any::<bool>(),
+        )
+            .prop_map(|(atoms, case_insensitive, start, end)| {
+                format!(
+                    "{}{}{}{}",
+                    if case_insensitive { "(?i)" } else { "" },
+                    if start { "^" } else { "" },
+                    atoms.concat(),
+                    if end { "$" } else { "" },
+                )
+            })
+    }
+
+    /// Bytes weighted towards pattern characters, line terminators and invalid UTF-8.
+    fn buffer() -> impl Strategy<Value = Vec<u8>> {
+        let byte = prop_oneof![
+            4 => prop::sample::select(b"abcABC ".to_vec()),
+            2 => Just(b'\n'),
+            1 => Just(b'\r'),
+            1 => Just(0xc3),
+            1 => Just(0xa9),
+            1 => Just(0xff),
+            1 => any::<u8>(),
+        ];
+        prop::collection::vec(byte, 0..200)
+    }
+
+    /// Splitting into lines first, the simplest way to get the right answer.
+    fn reference(buffer: &[u8], re: &bytes::Regex) -> Vec<LineMatch> {
+        let mut found = Vec::new();
+        let mut offset = 0;
+        let lines = buffer.split(|&b| b == b'\n').count();
+        for (idx, line) in buffer.split(|&b| b == b'\n').enumerate() {
+            let text = line.strip_suffix(b"\r").unwrap_or(line);
+            let terminator = match (idx + 1 == lines, text.len() < line.len()) {
+                (false, false) => LineTerminator::Lf,
+                (false, true) => LineTerminator::CrLf,
+                (true, false) => LineTerminator::None,
+                (true, true) => LineTerminator::Cr,
+            };
+            let submatches: Vec<_> = re
+                .find_iter(text)
+                .map(|m| m.range())
+                .filter(|range| !range.is_empty())
+                .collect();
+            if !submatches.is_empty() {
+                found.push(LineMatch {
+                    line_number: idx + 1,
+                    byte_offset: offset,
+                    line: text.to_vec(),
+                    terminator,
+                    submatches,
+                });
+            }
+            offset += line.len() + 1;
+        }
+        found
+    }
+
+    #[test]
+    fn test_search_lines_matches_each_line() {
+        let buffer =
+            b"needle first\nhay\r\nsecond Needle\r\n\nneedle needle\nno match\n^needle at end";
+
+        for pattern in [
+            "needle",
+            "(?i)needle",
+            "^needle",
+            r"needle\r?$",
+            r"\w+le",
+            "e.d",
+            "a*",
+        ] {
+            let re = bytes::Regex::new(pattern).unwrap();
+            assert_eq!(
+                search_lines(buffer, &re, &Prefilter::new(pattern)),
+                search_each_line(buffer, &re),
+                "Differs for {pattern}"
+            );
+        }
+
+        assert_eq!(count_lines(buffer), 6);
+    }
+
+    #[test]
+    fn test_empty_matches_are_skipped() {
+        let re = bytes::Regex::new("a*").unwrap();
+        let found = search_lines(b"baa\nbb\n", &re, &Prefilter::new("a*"));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].submatches, vec![1..3]);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_search_lines_matches_reference(buffer in buffer(), pattern in pattern()) {
+            let re = bytes::Regex::new(&pattern).unwrap();
+            let found = search_lines(&buffer, &re, &Prefilter::new(&pattern));
+
+            prop_assert_eq!(&found, &search_each_line(&buffer, &re));
+            prop_assert_eq!(found, reference(&buffer, &re));
+        }
+
+        #[test]
+        fn prop_line_matches_are_consistent(buffer in buffer(), pattern in pattern()) {
+            let re = bytes::Regex::new(&pattern).unwrap();
+            for found in search_lines(&buffer, &re, &Prefilter::new(&pattern)) {
+                let line = &buffer[found.byte_offset..found.byte_offset + found.line.len()];
+                prop_assert_eq!(line, &found.line[..]);
+                let terminator = found.terminator.as_bytes();
+                let after = &buffer[found.byte_offset + found.line.len()..];
+                prop_assert_eq!(&after[..terminator.len()], terminator);
+                prop_assert!(!found.line.contains(&b'\n'));
+                prop_assert_eq!(
+                    found.line_number,
+                    count_lines(&buffer[..found.byte_offset]) + 1
+                );
+                prop_assert!(!found.submatches.is_empty());
+                for range in &found.submatches {
+                    prop_assert!(range.start < range.end && range.end <= found.line.len());
+                }
+            }
+        }
+    }
+}
@@ -1,126 +1,119 @@
-use ansi_term::Colour;
+use ansi_term::{Colour, Style};
 use clap::Parser;
-use rustcomb::{get_cpuworkers, MyErrors, PrintEnabled, Printable};
+use rustcomb::{
+    MyErrors, PrintEnabled, Printable,
+    cancel::CancellationToken,
+    output::Output,
+    stats::Outcome,
+    strategy::{SearchStrategy, Strategy},
+    tuning::Tuning,
+};
 use std::{
     io::{self, BufWriter, Write},
-    sync::Arc,
     time::Instant,
 };
 use wild::args_os;
 
-async fn setup<P: Printable>(args: rustcomb::Cli, print_behaviour: P) -> Result<(), MyErrors> {
-    println!("Args: {:?}", args);
-    let cli = Arc::new(args);
-
-    let start = Instant::now();
-    rustcomb::single_thread_read_files(Arc::clone(&cli), print_behaviour)?;
-    let single_thread = start.elapsed();
-    let single_thread_print = format!(
-        "{}",
-        Colour::Green.paint(format!(
-            "Time taken for identifying files (single_thread_read_files): {:?}",
-            single_thread
-        ))
-    );
-    println!("{single_thread_print}");
-
-    let start = Instant::now();
-    rustcomb::thread_per_file_read_files(Arc::clone(&cli), print_behaviour)?;
-    let thread_per_file_elapsed = start.elapsed();
-    let thread_per_file_elapsed_print = format!(
-        "{}",
-        Colour::Green.paint(format!(
-            "Time taken for identifying files (use_thread_per_file): {:?}",
-            thread_per_file_elapsed
-        ))
-    );
-    println!("{thread_per_file_elapsed_print}");
-
-    let start = Instant::now();
-    rustcomb::threadpool_read_files(Arc::clone(&cli), print_behaviour, 1)?;
-    let threadpool_single_elapsed = start.elapsed();
-    let threadpool_single_elapsed_print = format!(
-        "{}",
-        Colour::Green.paint(format!(
-            "Time taken for identifying files (use_thread_pool - 1 thread): {:?}",
-            threadpool_single_elapsed
-        ))
-    );
-    println!("{threadpool_single_elapsed_print}");
+// A panicking search is caught and reported as `MyErrors::ThreadPanic`, which aborting would skip
+#[cfg(panic = "abort")]
+compile_error!("rustcomb needs `panic = \"unwind\"` to report a panicking search as an error");
 
-    let start = Instant::now();
-    let num_workers = get_cpuworkers();
-    rustcomb::threadpool_read_files(Arc::clone(&cli), print_behaviour, num_workers)?;
-    let threadpool_multiple_elapsed = start.elapsed();
-    let threadpool_multiple_elapsed_print = format!(
-        "{}",
-        Colour::Green.paint(format!(
-            "Time taken for identifying files (use_thread_pool - {} thread): {:?}",
-            num_workers, threadpool_multiple_elapsed
-        ))
-    );
-    println!("{threadpool_multiple_elapsed_print}");
-
-    let start = Instant::now();
-    rustcomb::rayon_read_files(Arc::clone(&cli), print_behaviour)?;
-    let rayon_elapsed = start.elapsed();
-    let rayon_elapsed_print = format!(
-        "{}",
-        Colour::Green.paint(format!(
-            "Time taken for identifying files (rayon_read_files): {:?}",
-            rayon_elapsed
-        ))
-    );
-    println!("{rayon_elapsed_print}");
+/// Timings aren't part of the results, so they are kept out of stdout when it is machine readable.
+fn report(machine_readable: bool, line: &str) {
+    if machine_readable {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
 
-    let start = Instant::now();
-    rustcomb::async_read_files(Arc::clone(&cli), print_behaviour).await?;
-    let async_elapsed = start.elapsed();
-    let async_elapsed_print = format!(
-        "{}",
-        Colour::Green.paint(format!(
-            "Time taken for identifying files (async_read_files): {:?}",
-            async_elapsed
-        ))
-    );
-    println!("{async_elapsed_print}");
+/// Once cancelled the remaining strategies aren't run.
+async fn setup<P: Printable>(
+    args: rustcomb::Cli,
+    print_behaviour: P,
+    cancel: CancellationToken,
+) -> Result<Outcome, MyErrors> {
+    let machine_readable = Output::from_cli(&args).is_machine_readable();
+    let timing_style = if args.color.use_colour() {
+        Colour::Green.normal()
+    } else {
+        Style::new()
+    };
+    report(machine_readable, &format!("Args: {:?}", args));
+
+    let mut outcome = Outcome::default();
+    let mut timings = Vec::new();
+    for strategy in Strategy::registered(Tuning::from_cli(&args).threads) {
+        if cancel.is_cancelled() {
+            return Ok(outcome);
+        }
+        let start = Instant::now();
+        outcome = outcome.merge(
+            strategy
+                .run(&args, print_behaviour.clone(), cancel.clone())
+                .await?,
+        );
+        let timing = format!(
+            "{}",
+            timing_style.paint(format!(
+                "Time taken for identifying files ({}): {:?}",
+                strategy.name(),
+                start.elapsed()
+            ))
+        );
+        report(machine_readable, &timing);
+        timings.push(timing);
+    }
 
-    let mut handle = BufWriter::new(io::stdout());
+    let mut handle: BufWriter<Box<dyn Write>> = if machine_readable {
+        BufWriter::new(Box::new(io::stderr()))
+    } else {
+        BufWriter::new(Box::new(io::stdout()))
+    };
     let mut output = String::new();
 
     output.push_str("\nSummary:\n");
-    output.push_str(&single_thread_print.to_string());
-    output.push('\n');
-    output.push_str(&thread_per_file_elapsed_print.to_string());
-    output.push('\n');
-    output.push_str(&threadpool_single_elapsed_print.to_string());
-    output.push('\n');
-    output.push_str(&threadpool_multiple_elapsed_print.to_string());
-    output.push('\n');
-    output.push_str(&rayon_elapsed_print.to_string());
-    output.push('\n');
-    output.push_str(&async_elapsed_print.to_string());
-    output.push('\n');
+    for timing in &timings {
+        output.push_str(timing);
+        output.push('\n');
+    }
 
-    handle.write_all(output.as_bytes()).map_err(MyErrors::FileIO)?;
+    handle
+        .write_all(output.as_bytes())
+        .map_err(MyErrors::Output)?;
 
-    Ok(())
+    Ok(outcome)
 }
 
 #[tokio::main]
 async fn main() {
     let cli = rustcomb::Cli::parse_from(args_os());
-    if let Err(e) = setup(cli, PrintEnabled).await {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+
+    // The first Ctrl-C stops the search but still writes what was found, a second exits immediately
+    let cancel = CancellationToken::new();
+    let handler_cancel = cancel.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            std::process::exit(130);
+        }
+        handler_cancel.cancel();
+    }) {
+        eprintln!("Unable to handle Ctrl-C: {}", e);
+    }
+
+    // grep's exit codes: 0 when something matched, 1 when nothing did and 2 for any error
+    match setup(cli, PrintEnabled, cancel).await {
+        Ok(outcome) => std::process::exit(outcome.exit_code()),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    // use assert_cmd::Command;
-    // use predicates::prelude::*;
 
     #[tokio::test]
     async fn test_setup_txt() {
@@ -132,7 +125,11 @@ mod tests {
         ];
         let cli = rustcomb::Cli::parse_from(args);
         // Use setup_with_args instead of setup to pass custom arguments
-        assert!(setup(cli, PrintEnabled).await.is_ok());
+        assert!(
+            setup(cli, PrintEnabled, CancellationToken::new())
+                .await
+                .is_ok()
+        );
     }
 
     #[tokio::test]
@@ -140,18 +137,10 @@ mod tests {
         let args = vec!["Rustcomb", "test_files", "metus mus. Elit convallis"];
         let cli = rustcomb::Cli::parse_from(args);
         // Use setup_with_args instead of setup to pass custom arguments
-        assert!(setup(cli, PrintEnabled).await.is_ok());
+        assert!(
+            setup(cli, PrintEnabled, CancellationToken::new())
+                .await
+                .is_ok()
+        );
     }
-
-    // #[test]
-    // fn test_run_main() {
-    //     let mut cmd = Command::cargo_bin("Rustcomb").unwrap();
-    //     cmd.args(["pattern", "path"])
-    //         .assert()
-    //         .failure()
-    //         .code(1)
-    //         .stderr(predicate::str::contains("Error message"));
-    //     cmd.assert().success();
-    //     // let output = cmd.unwrap();
-    // }
 }
@@ -0,0 +1,191 @@
+use std::fs::{File, Metadata};
+use std::io::Read;
+use std::ops::Deref;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use memmap2::{Mmap, MmapOptions};
+
+use crate::{MyErrors, Operation};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum MmapChoice {
+    #[default]
+    #[clap(
+        name = "auto",
+        help = "Memory map files unless they could change while being searched"
+    )]
+    Auto,
+    #[clap(
+        name = "always",
+        help = "Always memory map files, a file truncated while being searched crashes the process (SIGBUS)"
+    )]
+    Always,
+    #[clap(name = "never", help = "Always read files into a buffer")]
+    Never,
+}
+
+/// Files modified more recently than this are assumed to still be written to, e.g. a live log.
+const RECENTLY_MODIFIED: Duration = Duration::from_secs(30);
+
+/// The contents of a file, either mapped or read into memory depending on `MmapChoice`.
+pub enum FileContents {
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl FileContents {
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, FileContents::Mapped(_))
+    }
+}
+
+impl Deref for FileContents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileContents::Mapped(mmap) => mmap,
+            FileContents::Buffered(buffer) => buffer,
+        }
+    }
+}
+
+impl MmapChoice {
+    /**
+     * Maps the file when it's safe to, otherwise reads it into a buffer.
+     *
+     * A mapped file that is truncated while being searched raises SIGBUS rather than an error,
+     * which kills the process. With `auto` anything likely to change underneath us is buffered:
+     * recently modified files (growing logs), files on network filesystems and zero length files
+     * (procfs and sysfs report a length of 0 but still have contents when read).
+     */
+    pub fn read(self, path: &Path) -> Result<FileContents, MyErrors> {
+        let mut file = File::open(path).map_err(|e| MyErrors::io(path, Operation::Open, e))?;
+
+        let map = match self {
+            MmapChoice::Always => true,
+            MmapChoice::Never => false,
+            MmapChoice::Auto => file
+                .metadata()
+                .is_ok_and(|metadata| is_safe_to_map(&file, &metadata)),
+        };
+
+        if map {
+            // SAFETY: the file may still be changed by another process while mapped, which is
+            // the risk `auto` avoids and `always` opts in to.
+            let mmap = unsafe { MmapOptions::new().map(&file) }
+                .map_err(|e| MyErrors::io(path, Operation::Mmap, e))?;
+            return Ok(FileContents::Mapped(mmap));
+        }
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)
+            .map_err(|e| MyErrors::io(path, Operation::Read, e))?;
+        Ok(FileContents::Buffered(buffer))
+    }
+}
+
+fn is_safe_to_map(file: &File, metadata: &Metadata) -> bool {
+    metadata.len() > 0 && !recently_modified(metadata) && !is_network_filesystem(file)
+}
+
+/// Modification times in the future are treated as recent, platforms without them never are.
+fn recently_modified(metadata: &Metadata) -> bool {
+    match metadata.modified() {
+        Ok(modified) => SystemTime::now()
+            .duration_since(modified)
+            .map_or(true, |age| age < RECENTLY_MODIFIED),
+        Err(_) => false,
+    }
+}
+
+/// Another machine can truncate a file on a network filesystem without us knowing.
+/// Magic numbers are from statfs(2), FUSE is included as sshfs and similar are built on it.
+#[cfg(target_os = "linux")]
+fn is_network_filesystem(file: &File) -> bool {
+    use std::mem::MaybeUninit;
+    use std::os::fd::AsRawFd;
+
+    const NETWORK_FILESYSTEMS: [u32; 10] = [
+        0x6969,     // NFS
+        0x517B,     // SMB
+        0xFF534D42, // CIFS
+        0xFE534D42, // SMB2
+        0x5346414F, // AFS
+        0x00C36400, // Ceph
+        0x73757245, // Coda
+        0x01021997, // 9P, e.g. Windows drives under WSL2
+        0x65735546, // FUSE
+        0x01161970, // GFS2
+    ];
+
+    let mut stat = MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: fstatfs only writes to `stat`, which is only read when it succeeded
+    if unsafe { libc::fstatfs(file.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+        // Unknown, so assume the worst
+        return true;
+    }
+    let stat = unsafe { stat.assume_init() };
+
+    // `f_type`'s width varies by platform but every magic number fits within 32 bits
+    NETWORK_FILESYSTEMS.contains(&(stat.f_type as u32))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_network_filesystem(_file: &File) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    use assert_fs::TempDir;
+
+    use super::MmapChoice;
+
+    #[test]
+    fn test_always_and_never() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file.txt");
+        std::fs::write(&path, "needle\n").unwrap();
+
+        let mapped = MmapChoice::Always.read(&path).unwrap();
+        assert!(mapped.is_mapped());
+        assert_eq!(&*mapped, b"needle\n");
+
+        let buffered = MmapChoice::Never.read(&path).unwrap();
+        assert!(!buffered.is_mapped());
+        assert_eq!(&*buffered, b"needle\n");
+    }
+
+    #[test]
+    fn test_auto_buffers_risky_files() {
+        let dir = TempDir::new().unwrap();
+
+        let empty = dir.path().join("empty.txt");
+        std::fs::write(&empty, "").unwrap();
+        assert!(!MmapChoice::Auto.read(&empty).unwrap().is_mapped());
+
+        let growing = dir.path().join("growing.log");
+        std::fs::write(&growing, "needle\n").unwrap();
+        assert!(
+            !MmapChoice::Auto.read(&growing).unwrap().is_mapped(),
+            "Just written, so could still be growing"
+        );
+
+        File::options()
+            .write(true)
+            .open(&growing)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60 * 60))
+            .unwrap();
+        let settled = MmapChoice::Auto.read(&growing).unwrap();
+        assert_eq!(&*settled, b"needle\n");
+        if !super::is_network_filesystem(&File::open(&growing).unwrap()) {
+            assert!(settled.is_mapped());
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use std::str::FromStr;
 
-use regex::Regex;
+use regex::{Regex, bytes};
 
-use crate::MyErrors;
+use crate::prefilter::Prefilter;
+use crate::{LineMatch, MyErrors, lines};
 
 #[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
 pub enum SearchMode {
@@ -26,6 +27,57 @@ impl FromStr for SearchMode {
         }
     }
 }
+
+/**
+ * A pattern compiled once per run and shared (by reference, or an `Arc` for `'static` workers)
+ * with everything searching.
+ *
+ * Both forms of the regex are held: `bytes` for searching file contents without decoding them and
+ * `str` for the filenames and strategies which decode files first. Compiling is far more expensive
+ * than searching a typical file, so nothing should compile per file.
+ */
+#[derive(Clone, Debug)]
+pub struct Matcher {
+    regex: Regex,
+    bytes: bytes::Regex,
+    prefilter: Prefilter,
+}
+
+impl Matcher {
+    pub fn new(pattern: &str) -> Result<Self, MyErrors> {
+        Ok(Matcher {
+            regex: Regex::new(pattern).map_err(MyErrors::Regex)?,
+            bytes: bytes::Regex::new(pattern).map_err(MyErrors::Regex)?,
+            prefilter: Prefilter::new(pattern),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    pub fn bytes(&self) -> &bytes::Regex {
+        &self.bytes
+    }
+
+    pub fn prefilter(&self) -> &Prefilter {
+        &self.prefilter
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.regex.is_match(haystack)
+    }
+
+    /// Every matching line within `buffer`, see `lines::search_lines`.
+    pub fn find_lines(&self, buffer: &[u8]) -> Vec<LineMatch> {
+        lines::search_lines(buffer, &self.bytes, &self.prefilter)
+    }
+}
+
 /**
  * Use a single initialised re pattern to save it being created on each call (STAR_PATTERN)
  *
@@ -36,14 +88,14 @@ impl FromStr for SearchMode {
 pub fn clean_up_regex(
     pattern: Option<&str>,
     mode: SearchMode,
-) -> Result<Option<regex::Regex>, MyErrors> {
+) -> Result<Option<Matcher>, MyErrors> {
     pattern
         .map(|pat| {
             let s = match mode {
                 SearchMode::Literal => regex::escape(pat),
                 SearchMode::Regex => pat.to_string(),
             };
-            Regex::new(&s).map_err(MyErrors::Regex)
+            Matcher::new(&s)
         })
         .transpose()
 }
@@ -51,6 +103,7 @@ pub fn clean_up_regex(
 #[cfg(test)]
 mod tests {
     use super::{SearchMode, clean_up_regex};
+    use crate::prefilter::Prefilter;
 
     #[test]
     fn test_none() {
@@ -74,4 +127,18 @@ mod tests {
         let result = clean_up_regex(pattern, SearchMode::Regex).unwrap().unwrap();
         assert_eq!(result.as_str(), "Hello[ ]World");
     }
+
+    #[test]
+    fn test_matcher() {
+        let matcher = clean_up_regex(Some("(?i)hello world"), SearchMode::Regex)
+            .unwrap()
+            .unwrap();
+        assert!(matcher.is_match("HELLO World"));
+        assert!(matches!(matcher.prefilter(), Prefilter::Literals(_)));
+
+        let found = matcher.find_lines(b"nothing\nsay Hello World\n");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].line_number, 2);
+        assert_eq!(found[0].submatches, vec![4..15]);
+    }
 }
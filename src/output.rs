@@ -0,0 +1,634 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::colour::ColourSpecs;
+use crate::stats::StatsReport;
+use crate::{Cli, FileMatches, LineMatch, MyErrors, Operation};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Grouped per file, for people.
+    Standard,
+    /// One JSON object per line, following ripgrep's `--json` messages (begin, match, end, summary).
+    Json,
+    /// `path:line:column:text` for every match, as vim's `grepformat`.
+    Vimgrep,
+    /// `path:line:column: text` for every match, as Emacs' `compilation-mode`.
+    Emacs,
+}
+
+/// Bytes of output buffered before writing, matches common filesystem block sizes.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 64 * 1024;
+/// Output is flushed once this much is buffered, so results appear while a long search runs.
+pub const DEFAULT_FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// How results are written out, shared by every strategy.
+#[derive(Clone, Copy, Debug)]
+pub struct Output {
+    pub format: OutputFormat,
+    pub colours: ColourSpecs,
+    /// Whether `--stats` is written after the results.
+    pub stats: bool,
+    pub buffer_capacity: usize,
+    pub flush_threshold: usize,
+    started: Instant,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat, colours: ColourSpecs) -> Self {
+        Output {
+            format,
+            colours,
+            stats: false,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            flush_threshold: DEFAULT_FLUSH_THRESHOLD,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn from_cli(args: &Cli) -> Self {
+        let format = if args.json {
+            OutputFormat::Json
+        } else if args.vimgrep {
+            OutputFormat::Vimgrep
+        } else if args.emacs {
+            OutputFormat::Emacs
+        } else {
+            OutputFormat::Standard
+        };
+        Output {
+            stats: args.stats,
+            buffer_capacity: args.output_buffer,
+            flush_threshold: args.flush_threshold,
+            ..Output::new(format, ColourSpecs::new(args.color, &args.colors))
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Anything other than the standard format is intended to be parsed by other tools,
+    /// so shouldn't be mixed with extra information such as "Found {} files".
+    pub fn is_machine_readable(&self) -> bool {
+        self.format != OutputFormat::Standard
+    }
+
+    /// Writes all of the information for a single file with matches, returning the bytes written.
+    pub fn write_file<W: Write>(&self, w: &mut W, file: &FileMatches) -> io::Result<u64> {
+        let mut w = Counted::new(w);
+        let w = &mut w;
+        match self.format {
+            OutputFormat::Standard => {
+                writeln!(
+                    w,
+                    "Filename found with matches: {}",
+                    render_identifier(&file.filename, &file.path, &self.colours)
+                )?;
+                for line in &file.lines {
+                    writeln!(w, "{}", render_line(line, &self.colours))?;
+                }
+            }
+            OutputFormat::Json => write_file_json(w, file)?,
+            OutputFormat::Vimgrep => write_file_per_match(w, file, &self.colours, ":")?,
+            OutputFormat::Emacs => write_file_per_match(w, file, &self.colours, ": ")?,
+        }
+        Ok(w.count)
+    }
+
+    /**
+     * The summary message is only written for JSON, the standard format has its own "Found {} files" lines.
+     *
+     * `--stats` are included within the JSON summary, otherwise written as text. The per match formats
+     * send the text to stderr so it doesn't end up in an editor's list of matches.
+     */
+    pub fn write_summary<W: Write>(
+        &self,
+        w: &mut W,
+        report: &StatsReport,
+        with_stats: bool,
+    ) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                let message = Message::Summary {
+                    elapsed_total: Elapsed::from(self.started.elapsed()),
+                    stats: JsonStats::from(report),
+                    statistics: with_stats.then_some(report),
+                };
+                serde_json::to_writer(&mut *w, &message)?;
+                writeln!(w)
+            }
+            OutputFormat::Standard if with_stats => report.write_text(w),
+            OutputFormat::Vimgrep | OutputFormat::Emacs if with_stats => {
+                report.write_text(&mut io::stderr())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Running totals across every file written.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub searches_with_match: usize,
+    pub matched_lines: usize,
+    pub matches: usize,
+}
+
+impl Summary {
+    pub fn add(&mut self, file: &FileMatches) {
+        self.searches_with_match += 1;
+        self.matched_lines += file.lines.len();
+        self.matches += file.match_count();
+    }
+}
+
+pub fn render_identifier(filename: &str, path: &Path, colours: &ColourSpecs) -> String {
+    format!("{}", colours.path.paint(format!("{} {:?}", filename, path)))
+}
+
+/// `{line number}:{line}` with each match highlighted.
+pub fn render_line(line: &LineMatch, colours: &ColourSpecs) -> String {
+    let mut rendered = format!("{}:", colours.line.paint(format!("{}", line.line_number)));
+    let mut current_pos = 0;
+
+    for range in &line.submatches {
+        rendered.push_str(&String::from_utf8_lossy(
+            &line.line[current_pos..range.start],
+        ));
+        rendered.push_str(&format!(
+            "{}",
+            colours
+                .matched
+                .paint(String::from_utf8_lossy(&line.line[range.clone()]))
+        ));
+        current_pos = range.end;
+    }
+    rendered.push_str(&String::from_utf8_lossy(&line.line[current_pos..]));
+
+    rendered
+}
+
+/// One line per match (rather than per line) so every match can be jumped to from an editor.
+/// The column is the 1 based byte offset of the match within the line.
+fn write_file_per_match<W: Write>(
+    w: &mut W,
+    file: &FileMatches,
+    colours: &ColourSpecs,
+    text_separator: &str,
+) -> io::Result<()> {
+    let path = colours.path.paint(file.path.display().to_string());
+
+    for line in &file.lines {
+        let text = String::from_utf8_lossy(&line.line);
+        for range in &line.submatches {
+            writeln!(
+                w,
+                "{}:{}:{}{}{}",
+                path,
+                colours.line.paint(line.line_number.to_string()),
+                range.start + 1,
+                text_separator,
+                text
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_file_json<W: Write>(w: &mut W, file: &FileMatches) -> io::Result<()> {
+    let mut w = Counted::new(w);
+    let w = &mut w;
+    let path = Data::from_path(&file.path);
+
+    serde_json::to_writer(&mut *w, &Message::Begin { path: path.clone() })?;
+    writeln!(w)?;
+
+    for line in &file.lines {
+        let message = Message::Match {
+            path: path.clone(),
+            lines: Data::from_bytes(&[&line.line[..], line.terminator.as_bytes()].concat())
+                .into_owned(),
+            line_number: line.line_number,
+            absolute_offset: line.byte_offset,
+            submatches: line
+                .submatches
+                .iter()
+                .map(|range| SubMatch {
+                    matched: Data::from_bytes(&line.line[range.clone()]),
+                    start: range.start,
+                    end: range.end,
+                })
+                .collect(),
+        };
+        serde_json::to_writer(&mut *w, &message)?;
+        writeln!(w)?;
+    }
+
+    let message = Message::End {
+        path,
+        binary_offset: None,
+        stats: JsonStats {
+            elapsed: Elapsed::from(file.elapsed),
+            searches: 1,
+            searches_with_match: 1,
+            bytes_searched: file.bytes_searched,
+            bytes_printed: w.count,
+            matched_lines: file.lines.len(),
+            matches: file.match_count(),
+        },
+    };
+    serde_json::to_writer(&mut *w, &message)?;
+    writeln!(w)
+}
+
+/// An error for a single file as a JSON `error` message, the message is the underlying cause without the path.
+pub fn write_error_json<W: Write>(w: &mut W, path: &Path, err: &MyErrors) -> io::Result<()> {
+    let message = Message::Error {
+        path: Data::from_path(err.path().unwrap_or(path)),
+        code: err.code(),
+        operation: err.operation(),
+        message: err
+            .source()
+            .map_or_else(|| err.to_string(), |source| source.to_string()),
+    };
+    serde_json::to_writer(&mut *w, &message)?;
+    writeln!(w)
+}
+
+/// Mirrors ripgrep's JSON Lines schema, see `rg --json`.
+#[derive(Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+enum Message<'a> {
+    Begin {
+        path: Data<'a>,
+    },
+    Match {
+        path: Data<'a>,
+        lines: Data<'a>,
+        line_number: usize,
+        absolute_offset: usize,
+        submatches: Vec<SubMatch<'a>>,
+    },
+    End {
+        path: Data<'a>,
+        binary_offset: Option<u64>,
+        stats: JsonStats,
+    },
+    /// Not part of ripgrep's schema, written to stderr for errors with a single file
+    Error {
+        path: Data<'a>,
+        code: &'static str,
+        operation: Option<Operation>,
+        message: String,
+    },
+    Summary {
+        elapsed_total: Elapsed,
+        stats: JsonStats,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        statistics: Option<&'a StatsReport>,
+    },
+}
+
+/// ripgrep's stats, for a single file in `end` and the whole run in `summary`.
+/// `bytes_printed` for a file excludes its `end` message, as ripgrep's does.
+#[derive(Serialize)]
+struct JsonStats {
+    /// Time spent searching, summed across threads for the whole run
+    elapsed: Elapsed,
+    searches: usize,
+    searches_with_match: usize,
+    bytes_searched: u64,
+    bytes_printed: u64,
+    matched_lines: usize,
+    matches: usize,
+}
+
+impl From<&StatsReport> for JsonStats {
+    fn from(report: &StatsReport) -> Self {
+        JsonStats {
+            elapsed: report.search.clone(),
+            searches: report.files_searched,
+            searches_with_match: report.files_with_match,
+            bytes_searched: report.bytes_searched,
+            bytes_printed: report.bytes_printed,
+            matched_lines: report.matched_lines,
+            matches: report.matches,
+        }
+    }
+}
+
+/// Counts the bytes written through it, for `bytes_printed`.
+struct Counted<'a, W> {
+    inner: &'a mut W,
+    count: u64,
+}
+
+impl<'a, W: Write> Counted<'a, W> {
+    fn new(inner: &'a mut W) -> Self {
+        Counted { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for Counted<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/**
+ * ripgrep's arbitrary data: `{"text": ...}` when it's valid UTF-8, otherwise `{"bytes": ...}` with
+ * the raw bytes base64 encoded. Offsets (e.g. a submatch's `start`) are always into the raw bytes,
+ * so they only line up with the data when it isn't decoded lossily.
+ */
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Data<'a> {
+    Text(Cow<'a, str>),
+    Bytes(String),
+}
+
+impl<'a> Data<'a> {
+    fn from_bytes(bytes: &'a [u8]) -> Self {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Data::Text(Cow::Borrowed(text)),
+            Err(_) => Data::Bytes(base64(bytes)),
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_path(path: &'a Path) -> Self {
+        use std::os::unix::ffi::OsStrExt;
+        Data::from_bytes(path.as_os_str().as_bytes())
+    }
+
+    #[cfg(not(unix))]
+    fn from_path(path: &'a Path) -> Self {
+        Data::Text(path.to_string_lossy())
+    }
+
+    fn into_owned(self) -> Data<'static> {
+        match self {
+            Data::Text(text) => Data::Text(Cow::Owned(text.into_owned())),
+            Data::Bytes(bytes) => Data::Bytes(bytes),
+        }
+    }
+}
+
+/// Standard base64 with padding, as ripgrep encodes `bytes`.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[derive(Serialize)]
+struct SubMatch<'a> {
+    #[serde(rename = "match")]
+    matched: Data<'a>,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Elapsed {
+    pub secs: u64,
+    pub nanos: u32,
+    pub human: String,
+}
+
+impl From<Duration> for Elapsed {
+    fn from(duration: Duration) -> Self {
+        Elapsed {
+            secs: duration.as_secs(),
+            nanos: duration.subsec_nanos(),
+            human: format!("{:.6}s", duration.as_secs_f64()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::{Output, OutputFormat, Summary, base64, render_line};
+    use crate::colour::ColourSpecs;
+    use crate::lines::LineTerminator;
+    use crate::stats::Stats;
+    use crate::{FileMatches, LineMatch};
+
+    fn example() -> FileMatches {
+        FileMatches {
+            path: PathBuf::from("dir/example.txt"),
+            filename: "example.txt".to_string(),
+            lines: vec![LineMatch {
+                line_number: 3,
+                byte_offset: 20,
+                line: b"a needle and a needle".to_vec(),
+                terminator: LineTerminator::Lf,
+                submatches: vec![2..8, 15..21],
+            }],
+            bytes_searched: 100,
+            elapsed: Duration::from_millis(3),
+        }
+    }
+
+    #[test]
+    fn test_json_messages() {
+        let output = Output::new(OutputFormat::Json, ColourSpecs::plain());
+        let mut buffer = Vec::new();
+        output.write_file(&mut buffer, &example()).unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(buffer)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            serde_json::json!({"type": "begin", "data": {"path": {"text": "dir/example.txt"}}})
+        );
+        assert_eq!(
+            lines[1],
+            serde_json::json!({
+                "type": "match",
+                "data": {
+                    "path": {"text": "dir/example.txt"},
+                    "lines": {"text": "a needle and a needle\n"},
+                    "line_number": 3,
+                    "absolute_offset": 20,
+                    "submatches": [
+                        {"match": {"text": "needle"}, "start": 2, "end": 8},
+                        {"match": {"text": "needle"}, "start": 15, "end": 21},
+                    ],
+                }
+            })
+        );
+        assert_eq!(lines[2]["type"], "end");
+        let printed = lines[0].to_string().len() + lines[1].to_string().len() + 2;
+        assert_eq!(
+            lines[2]["data"]["stats"],
+            serde_json::json!({
+                "elapsed": {"secs": 0, "nanos": 3_000_000, "human": "0.003000s"},
+                "searches": 1,
+                "searches_with_match": 1,
+                "bytes_searched": 100,
+                "bytes_printed": printed,
+                "matched_lines": 1,
+                "matches": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_json_keeps_raw_bytes_and_terminators() {
+        let file = FileMatches {
+            path: PathBuf::from("latin1.txt"),
+            filename: "latin1.txt".to_string(),
+            lines: vec![
+                LineMatch {
+                    line_number: 1,
+                    byte_offset: 0,
+                    line: b"caf\xe9 needle".to_vec(),
+                    terminator: LineTerminator::CrLf,
+                    submatches: vec![Range { start: 5, end: 11 }],
+                },
+                LineMatch {
+                    line_number: 2,
+                    byte_offset: 13,
+                    line: b"last needle".to_vec(),
+                    terminator: LineTerminator::None,
+                    submatches: vec![Range { start: 5, end: 11 }],
+                },
+            ],
+            bytes_searched: 24,
+            elapsed: Duration::ZERO,
+        };
+        let mut buffer = Vec::new();
+        Output::new(OutputFormat::Json, ColourSpecs::plain())
+            .write_file(&mut buffer, &file)
+            .unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(buffer)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        // Not valid UTF-8, so `start` and `end` are offsets into the decoded bytes
+        assert_eq!(
+            lines[1]["data"]["lines"],
+            serde_json::json!({"bytes": "Y2Fm6SBuZWVkbGUNCg=="})
+        );
+        assert_eq!(
+            lines[1]["data"]["submatches"][0],
+            serde_json::json!({"match": {"text": "needle"}, "start": 5, "end": 11})
+        );
+        assert_eq!(
+            lines[2]["data"]["lines"],
+            serde_json::json!({"text": "last needle"})
+        );
+    }
+
+    #[test]
+    fn test_base64() {
+        let encoded: Vec<String> = ["", "f", "fo", "foo", "foob", "fooba", "foobar"]
+            .iter()
+            .map(|s| base64(s.as_bytes()))
+            .collect();
+        assert_eq!(
+            encoded,
+            [
+                "", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"
+            ]
+        );
+        assert_eq!(base64(&[0xff, 0xfe, 0x00]), "//4A");
+    }
+
+    #[test]
+    fn test_summary_only_written_for_json() {
+        let mut summary = Summary::default();
+        summary.add(&example());
+        let stats = Stats::default();
+        stats.searched(100);
+        stats.searched(50);
+        stats.printed(300);
+        let report = stats.report(&summary);
+
+        let mut buffer = Vec::new();
+        Output::new(OutputFormat::Standard, ColourSpecs::plain())
+            .write_summary(&mut buffer, &report, false)
+            .unwrap();
+        assert!(buffer.is_empty());
+
+        Output::new(OutputFormat::Json, ColourSpecs::plain())
+            .write_summary(&mut buffer, &report, false)
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&buffer).unwrap();
+        assert_eq!(value["type"], "summary");
+        let stats = &value["data"]["stats"];
+        assert_eq!(stats["searches"], 2);
+        assert_eq!(stats["searches_with_match"], 1);
+        assert_eq!(stats["bytes_searched"], 150);
+        assert_eq!(stats["bytes_printed"], 300);
+        assert_eq!(stats["matched_lines"], 1);
+        assert_eq!(stats["matches"], 2);
+        assert!(stats["elapsed"]["human"].is_string());
+        assert!(value["data"].get("statistics").is_none());
+    }
+
+    #[test]
+    fn test_render_line_plain() {
+        let rendered = render_line(&example().lines[0], &ColourSpecs::plain());
+        assert_eq!(rendered, "3:a needle and a needle");
+    }
+
+    #[test]
+    fn test_vimgrep_and_emacs() {
+        let mut buffer = Vec::new();
+        Output::new(OutputFormat::Vimgrep, ColourSpecs::plain())
+            .write_file(&mut buffer, &example())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "dir/example.txt:3:3:a needle and a needle\ndir/example.txt:3:16:a needle and a needle\n"
+        );
+
+        let mut buffer = Vec::new();
+        Output::new(OutputFormat::Emacs, ColourSpecs::plain())
+            .write_file(&mut buffer, &example())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "dir/example.txt:3:3: a needle and a needle\ndir/example.txt:3:16: a needle and a needle\n"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use aho_corasick::{AhoCorasick, MatchKind};
+use memchr::memmem;
+use regex_syntax::ParserBuilder;
+use regex_syntax::hir::literal::Extractor;
+
+/// Above this many literals the automaton costs more than running the regex on every line.
+const MAX_LITERALS: usize = 64;
+
+/**
+ * Finds where a match could start without running the regex.
+ *
+ * Every match of the pattern has to begin with one of its prefix literals (e.g. `cubilia` for
+ * `cubilia \w+`), so the buffer is searched for those with SIMD accelerated substring search and
+ * only lines containing one are handed to the regex. Patterns without usable literals (such as
+ * `\w+ing`) produce `Prefilter::None` and every line is checked.
+ */
+#[derive(Clone, Debug)]
+pub enum Prefilter {
+    Memmem(Box<memmem::Finder<'static>>),
+    Literals(AhoCorasick),
+    None,
+}
+
+impl Prefilter {
+    pub fn new(pattern: &str) -> Self {
+        // `bytes::Regex` accepts patterns matching invalid UTF-8, so the parser has to as well
+        let hir = match ParserBuilder::new().utf8(false).build().parse(pattern) {
+            Ok(hir) => hir,
+            Err(_) => return Prefilter::None,
+        };
+
+        let mut prefixes = Extractor::new().extract(&hir);
+        prefixes.optimize_for_prefix_by_preference();
+        let literals = match prefixes.literals() {
+            Some(literals)
+                if !literals.is_empty()
+                    && literals.len() <= MAX_LITERALS
+                    && literals.iter().all(|lit| !lit.as_bytes().is_empty()) =>
+            {
+                literals
+            }
+            // Infinite, too many or something which can start with nothing, e.g. `a*`
+            _ => return Prefilter::None,
+        };
+
+        match literals {
+            [literal] => Prefilter::Memmem(Box::new(
+                memmem::Finder::new(literal.as_bytes()).into_owned(),
+            )),
+            _ => AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostFirst)
+                .build(literals.iter().map(|lit| lit.as_bytes()))
+                .map_or(Prefilter::None, Prefilter::Literals),
+        }
+    }
+
+    /// Offset of the earliest possible match within `haystack`.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        match self {
+            Prefilter::Memmem(finder) => finder.find(haystack),
+            Prefilter::Literals(automaton) => automaton.find(haystack).map(|m| m.start()),
+            Prefilter::None => (!haystack.is_empty()).then_some(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Prefilter;
+
+    #[test]
+    fn test_prefilter_kinds() {
+        assert!(matches!(Prefilter::new("cubilia"), Prefilter::Memmem(_)));
+        assert!(matches!(Prefilter::new("^cubilia$"), Prefilter::Memmem(_)));
+        assert!(matches!(
+            Prefilter::new("(?i)cubilia"),
+            Prefilter::Literals(_)
+        ));
+        assert!(matches!(Prefilter::new("foo|bar"), Prefilter::Literals(_)));
+        assert!(matches!(Prefilter::new(r"\w+ing"), Prefilter::None));
+        assert!(matches!(Prefilter::new("a*"), Prefilter::None));
+        assert!(matches!(Prefilter::new("("), Prefilter::None));
+
+        let prefilter = Prefilter::new("foo|bar");
+        assert_eq!(prefilter.find(b"xx bar foo"), Some(3));
+        assert_eq!(prefilter.find(b"nothing"), None);
+        assert_eq!(Prefilter::None.find(b"anything"), Some(0));
+    }
+}
@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use walkdir::WalkDir;
+
+use crate::Cli;
+use crate::input::Input;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum SortBy {
+    #[default]
+    #[clap(name = "none", help = "Results are printed as soon as they are found")]
+    None,
+    #[clap(name = "path", help = "Sort by file path")]
+    Path,
+    #[clap(name = "modified", help = "Sort by the last modified time")]
+    Modified,
+    #[clap(name = "accessed", help = "Sort by the last accessed time")]
+    Accessed,
+    #[clap(name = "created", help = "Sort by the creation time")]
+    Created,
+}
+
+/// The order files are walked in, and so the order their results are printed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sort {
+    pub by: SortBy,
+    pub reverse: bool,
+}
+
+impl Sort {
+    pub fn from_cli(args: &Cli) -> Self {
+        match args.sortr {
+            Some(by) => Sort { by, reverse: true },
+            None => Sort {
+                by: args.sort,
+                reverse: false,
+            },
+        }
+    }
+
+    /// Whether the parallel strategies need to put their results back into walk order.
+    pub fn is_ordered(&self) -> bool {
+        self.by != SortBy::None
+    }
+
+    /// Path sorting is handled by the walker itself, one directory at a time.
+    pub fn walker(&self, path: &Path) -> WalkDir {
+        let walker = WalkDir::new(path);
+        match (self.by, self.reverse) {
+            (SortBy::Path, false) => walker.sort_by(|a, b| a.file_name().cmp(b.file_name())),
+            (SortBy::Path, true) => walker.sort_by(|a, b| b.file_name().cmp(a.file_name())),
+            _ => walker,
+        }
+    }
+
+    /// Sorting by paths given to us (rather than walked) such as with `--files-from`.
+    pub fn sort_inputs(&self, inputs: &mut [Input]) {
+        if self.by != SortBy::Path {
+            return;
+        }
+
+        inputs.sort_by(|a, b| {
+            let ordering = match (a, b) {
+                (Input::Path(a), Input::Path(b)) => a.cmp(b),
+                (Input::Stdin(_), _) => Ordering::Less,
+                (_, Input::Stdin(_)) => Ordering::Greater,
+            };
+            self.direction(ordering)
+        });
+    }
+
+    pub fn needs_metadata(&self) -> bool {
+        matches!(
+            self.by,
+            SortBy::Modified | SortBy::Accessed | SortBy::Created
+        )
+    }
+
+    /**
+     * Timestamps can't be compared a directory at a time, so every walked path is collected
+     * and sorted before any searching starts. Only the paths are held, not their results.
+     * Files without the timestamp (e.g. unsupported by the platform) are placed last.
+     */
+    pub fn sort_by_metadata(&self, inputs: Vec<Input>) -> Vec<Input> {
+        let accessor: fn(&Metadata) -> io::Result<SystemTime> = match self.by {
+            SortBy::Modified => Metadata::modified,
+            SortBy::Accessed => Metadata::accessed,
+            SortBy::Created => Metadata::created,
+            SortBy::None | SortBy::Path => return inputs,
+        };
+
+        let mut keyed: Vec<(Option<SystemTime>, Input)> = inputs
+            .into_iter()
+            .map(|input| {
+                let time = match &input {
+                    Input::Path(path) => std::fs::metadata(path).and_then(|m| accessor(&m)).ok(),
+                    Input::Stdin(_) => None,
+                };
+                (time, input)
+            })
+            .collect();
+
+        keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Some(a), Some(b)) => self.direction(a.cmp(b)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+
+        keyed.into_iter().map(|(_, input)| input).collect()
+    }
+
+    fn direction(&self, ordering: Ordering) -> Ordering {
+        if self.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/**
+ * Puts results from parallel workers back into walk order without waiting for all of them.
+ *
+ * Every file walked is given a sequence number, and workers hand back `None` for files without
+ * matches so the buffer knows it can move past them. Only results which arrived ahead of a slower
+ * file are held in memory.
+ */
+pub struct ReorderBuffer<T> {
+    next: usize,
+    pending: BTreeMap<usize, Option<T>>,
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        ReorderBuffer {
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    pub fn push(&mut self, seq: usize, item: Option<T>) {
+        self.pending.insert(seq, item);
+    }
+
+    /// The next result in walk order, if it (and everything before it) has arrived.
+    pub fn pop(&mut self) -> Option<T> {
+        while let Some(item) = self.pending.remove(&self.next) {
+            self.next += 1;
+            if item.is_some() {
+                return item;
+            }
+        }
+        None
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Everything still held once nothing more will arrive, in order, and how many results never did.
+    pub fn drain(&mut self) -> (usize, Vec<T>) {
+        let pending = std::mem::take(&mut self.pending);
+        let missing = pending
+            .keys()
+            .next_back()
+            .map_or(0, |last| last + 1 - self.next - pending.len());
+        self.next += missing + pending.len();
+        (missing, pending.into_values().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{ReorderBuffer, Sort, SortBy};
+    use crate::input::Input;
+
+    #[test]
+    fn test_reorder_buffer() {
+        let mut buffer = ReorderBuffer::default();
+
+        buffer.push(2, Some("c"));
+        buffer.push(1, None);
+        assert_eq!(buffer.pop(), None, "Waiting on the first result");
+
+        buffer.push(0, Some("a"));
+        assert_eq!(buffer.pop(), Some("a"));
+        assert_eq!(buffer.pop(), Some("c"), "Skips the file without matches");
+        assert_eq!(buffer.pop(), None);
+
+        buffer.push(3, Some("d"));
+        assert_eq!(buffer.pop(), Some("d"));
+        assert!(buffer.is_empty());
+
+        // Results 4 and 6 never arrive
+        buffer.push(5, Some("f"));
+        buffer.push(7, None);
+        buffer.push(8, Some("i"));
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(buffer.drain(), (2, vec!["f", "i"]));
+        assert!(buffer.is_empty());
+        buffer.push(9, Some("j"));
+        assert_eq!(buffer.pop(), Some("j"));
+    }
+
+    #[test]
+    fn test_sort_inputs_by_path() {
+        let mut inputs = vec![
+            Input::Path(PathBuf::from("b")),
+            Input::Path(PathBuf::from("c")),
+            Input::Path(PathBuf::from("a")),
+        ];
+        let sort = Sort {
+            by: SortBy::Path,
+            reverse: true,
+        };
+        sort.sort_inputs(&mut inputs);
+
+        let paths: Vec<PathBuf> = inputs
+            .into_iter()
+            .map(|input| match input {
+                Input::Path(path) => path,
+                Input::Stdin(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("c"), PathBuf::from("b"), PathBuf::from("a")]
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::output::{self, Elapsed, Output, Summary};
+use crate::{Cli, MyErrors, Operation};
+
+/// The stages of a run which are timed separately.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Walk,
+    Search,
+    Print,
+}
+
+/// An error for a single file which didn't stop the rest of the run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub operation: Option<Operation>,
+    /// The underlying cause, without the path
+    pub message: String,
+}
+
+/**
+ * Counters shared by the walker, every searching thread and the printer for a single run.
+ *
+ * Everything is atomic (or behind a lock for the rarely touched errors) so it can be shared as an `Arc`
+ * without any of the strategies coordinating. Stage timings are summed across threads, so the search
+ * time of a parallel strategy can exceed the wall clock time.
+ *
+ * Errors for individual files are also collected here, grouped by their `MyErrors::code`, so they can be
+ * summarised at the end of the run and reflected in the exit code.
+ */
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// `--no-messages`, errors are still counted but not written
+    no_messages: bool,
+    /// `--json`, errors are written to stderr as JSON `error` messages
+    json: bool,
+    files_walked: AtomicUsize,
+    files_filtered: AtomicUsize,
+    files_searched: AtomicUsize,
+    bytes_searched: AtomicU64,
+    bytes_printed: AtomicU64,
+    errors: Mutex<BTreeMap<&'static str, Vec<FileError>>>,
+    walk_nanos: AtomicU64,
+    search_nanos: AtomicU64,
+    print_nanos: AtomicU64,
+}
+
+impl Stats {
+    pub fn new(no_messages: bool) -> Self {
+        Stats {
+            no_messages,
+            ..Stats::default()
+        }
+    }
+
+    pub fn from_cli(args: &Cli) -> Self {
+        Stats {
+            json: Output::from_cli(args).is_json(),
+            ..Stats::new(args.no_messages)
+        }
+    }
+
+    /// A file found while walking, `filtered` when the path pattern rejected it.
+    pub fn walked(&self, filtered: bool) {
+        self.files_walked.fetch_add(1, Ordering::Relaxed);
+        if filtered {
+            self.files_filtered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn searched(&self, bytes: usize) {
+        self.files_searched.fetch_add(1, Ordering::Relaxed);
+        self.bytes_searched
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn printed(&self, bytes: u64) {
+        self.bytes_printed.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Reports an error for a single file as it happens, keeping it for the end of run summary.
+    /// `path` is only used for errors which don't carry their own, it's empty when there isn't one.
+    pub fn error(&self, path: &Path, err: &MyErrors) {
+        if !self.no_messages {
+            if self.json {
+                // Nothing else can be done if stderr itself can't be written to
+                let _ = output::write_error_json(&mut io::stderr().lock(), path, err);
+            } else if err.path().is_some() || path.as_os_str().is_empty() {
+                eprintln!("{}", err);
+            } else {
+                eprintln!("{}: {}", path.display(), err);
+            }
+        }
+
+        // A poisoned lock only means another thread panicked mid-push, the errors are still usable
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        errors.entry(err.code()).or_default().push(FileError {
+            path: err.path().unwrap_or(path).to_path_buf(),
+            operation: err.operation(),
+            message: err
+                .source()
+                .map_or_else(|| err.to_string(), |source| source.to_string()),
+        });
+    }
+
+    pub fn error_count(&self) -> usize {
+        let errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        errors.values().map(Vec::len).sum()
+    }
+
+    /// Every file error grouped by code, nothing is written with `--no-messages`, `--json` or without errors.
+    pub fn write_errors<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        if self.no_messages || self.json || errors.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(w, "Errors:")?;
+        for (code, file_errors) in errors.iter() {
+            writeln!(w, "  {} ({}):", code, file_errors.len())?;
+            for file_error in file_errors {
+                writeln!(
+                    w,
+                    "    {}: {}",
+                    file_error.path.display(),
+                    file_error.message
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn outcome(&self, summary: &Summary) -> Outcome {
+        Outcome {
+            matched: summary.searches_with_match > 0,
+            errors: self.error_count(),
+            cancelled: false,
+        }
+    }
+
+    pub fn time<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add_time(stage, start.elapsed());
+        result
+    }
+
+    pub fn add_time(&self, stage: Stage, elapsed: Duration) {
+        let nanos = match stage {
+            Stage::Walk => &self.walk_nanos,
+            Stage::Search => &self.search_nanos,
+            Stage::Print => &self.print_nanos,
+        };
+        nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Match counts come from the printer's summary as only it sees every result.
+    pub fn report(&self, summary: &Summary) -> StatsReport {
+        let errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        let nanos = |n: &AtomicU64| Elapsed::from(Duration::from_nanos(n.load(Ordering::Relaxed)));
+
+        StatsReport {
+            files_walked: self.files_walked.load(Ordering::Relaxed),
+            files_filtered: self.files_filtered.load(Ordering::Relaxed),
+            files_searched: self.files_searched.load(Ordering::Relaxed),
+            bytes_searched: self.bytes_searched.load(Ordering::Relaxed),
+            bytes_printed: self.bytes_printed.load(Ordering::Relaxed),
+            files_with_match: summary.searches_with_match,
+            matched_lines: summary.matched_lines,
+            matches: summary.matches,
+            errors: errors
+                .iter()
+                .map(|(code, file_errors)| (*code, file_errors.len()))
+                .collect(),
+            walk: nanos(&self.walk_nanos),
+            search: nanos(&self.search_nanos),
+            print: nanos(&self.print_nanos),
+        }
+    }
+}
+
+/// What a run found, used for grep's exit codes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Outcome {
+    pub matched: bool,
+    pub errors: usize,
+    /// Stopped early by a `CancellationToken`, e.g. Ctrl-C
+    pub cancelled: bool,
+}
+
+impl Outcome {
+    /// Combines the outcomes of several runs, e.g. every strategy.
+    pub fn merge(self, other: Outcome) -> Outcome {
+        Outcome {
+            matched: self.matched || other.matched,
+            errors: self.errors + other.errors,
+            cancelled: self.cancelled || other.cancelled,
+        }
+    }
+
+    /// As grep: 0 when something matched, 1 when nothing did and 2 when there was an error (even with matches).
+    /// A cancelled run exits with 130, as shells do for SIGINT.
+    pub fn exit_code(&self) -> i32 {
+        if self.cancelled {
+            130
+        } else if self.errors > 0 {
+            2
+        } else if self.matched {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+/// A point in time copy of `Stats`, written as text or within the JSON summary.
+#[derive(Debug, Serialize)]
+pub struct StatsReport {
+    pub files_walked: usize,
+    pub files_filtered: usize,
+    pub files_searched: usize,
+    pub bytes_searched: u64,
+    pub bytes_printed: u64,
+    pub files_with_match: usize,
+    pub matched_lines: usize,
+    pub matches: usize,
+    /// Counts keyed by `MyErrors::code`
+    pub errors: BTreeMap<&'static str, usize>,
+    pub walk: Elapsed,
+    pub search: Elapsed,
+    pub print: Elapsed,
+}
+
+impl StatsReport {
+    pub fn write_text<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let errors = if self.errors.is_empty() {
+            "none".to_string()
+        } else {
+            self.errors
+                .iter()
+                .map(|(code, count)| format!("{}={}", code, count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        writeln!(w, "Stats:")?;
+        writeln!(w, "  files walked:     {}", self.files_walked)?;
+        writeln!(w, "  files filtered:   {}", self.files_filtered)?;
+        writeln!(w, "  files searched:   {}", self.files_searched)?;
+        writeln!(w, "  bytes searched:   {}", self.bytes_searched)?;
+        writeln!(w, "  bytes printed:    {}", self.bytes_printed)?;
+        writeln!(w, "  files with match: {}", self.files_with_match)?;
+        writeln!(w, "  matched lines:    {}", self.matched_lines)?;
+        writeln!(w, "  matches:          {}", self.matches)?;
+        writeln!(w, "  errors:           {}", errors)?;
+        writeln!(
+            w,
+            "  elapsed:          walk {}, search {}, print {} (summed across threads)",
+            self.walk.human, self.search.human, self.print.human
+        )
+    }
+}
+
+/// Adds the time spent producing each item to the walk stage.
+pub struct TimedWalk<I> {
+    inner: I,
+    stats: Arc<Stats>,
+}
+
+impl<I> TimedWalk<I> {
+    pub fn new(inner: I, stats: Arc<Stats>) -> Self {
+        TimedWalk { inner, stats }
+    }
+}
+
+impl<I: Iterator> Iterator for TimedWalk<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.stats.time(Stage::Walk, || self.inner.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use std::path::Path;
+
+    use super::{Outcome, Stage, Stats};
+    use crate::output::Summary;
+    use crate::{MyErrors, Operation};
+
+    #[test]
+    fn test_report() {
+        let stats = Stats::default();
+        stats.walked(false);
+        stats.walked(true);
+        stats.searched(10);
+        stats.printed(5);
+        stats.printed(7);
+        stats.error(
+            Path::new("ignored"),
+            &MyErrors::io("a", Operation::Read, io::Error::other("failed")),
+        );
+        stats.error(
+            Path::new("b"),
+            &MyErrors::ThreadPanic("failed again".to_string()),
+        );
+        stats.add_time(Stage::Search, std::time::Duration::from_millis(2));
+
+        let report = stats.report(&Summary {
+            searches_with_match: 1,
+            matched_lines: 2,
+            matches: 3,
+        });
+        assert_eq!(report.files_walked, 2);
+        assert_eq!(report.files_filtered, 1);
+        assert_eq!(report.files_searched, 1);
+        assert_eq!(report.bytes_searched, 10);
+        assert_eq!(report.bytes_printed, 12);
+        assert_eq!(report.matches, 3);
+        assert_eq!(report.errors.get("io_read"), Some(&1));
+        assert_eq!(report.errors.get("thread_panic"), Some(&1));
+        assert_eq!(report.search.secs, 0);
+        assert_eq!(report.search.nanos, 2_000_000);
+
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["errors"]["io_read"], 1);
+        assert_eq!(value["matched_lines"], 2);
+
+        let mut buffer = Vec::new();
+        stats.write_errors(&mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "Errors:\n  io_read (1):\n    a: failed\n  thread_panic (1):\n    b: thread error (failed again)\n"
+        );
+    }
+
+    #[test]
+    fn test_outcome_exit_codes() {
+        let nothing = Outcome::default();
+        let matched = Outcome {
+            matched: true,
+            ..Outcome::default()
+        };
+        let errored = Outcome {
+            errors: 1,
+            ..Outcome::default()
+        };
+        let cancelled = Outcome {
+            cancelled: true,
+            ..Outcome::default()
+        };
+
+        assert_eq!(nothing.exit_code(), 1);
+        assert_eq!(matched.exit_code(), 0);
+        assert_eq!(errored.exit_code(), 2);
+        assert_eq!(matched.merge(errored).exit_code(), 2);
+        assert_eq!(nothing.merge(matched).exit_code(), 0);
+        assert_eq!(errored.merge(cancelled).exit_code(), 130);
+    }
+}
@@ -0,0 +1,250 @@
+use std::future::Future;
+
+use crate::cancel::CancellationToken;
+use crate::stats::Outcome;
+use crate::{Cli, MyErrors, Printable, Search};
+
+/**
+ * A way of searching every file, given the walk and matcher already set up in a `Search`.
+ *
+ * Adding a backend is an implementation of this and a variant of `Strategy`, which the CLI and
+ * benches run. The threaded strategies block until they're done, so run on Tokio's blocking
+ * threads rather than holding up a worker.
+ */
+pub trait SearchStrategy {
+    /// Reported alongside the time taken, and used as the benchmark's ID.
+    fn name(&self) -> String;
+
+    fn search<P: Printable>(
+        &self,
+        search: Search,
+        print_behaviour: P,
+    ) -> impl Future<Output = Result<Outcome, MyErrors>>;
+}
+
+/// Every file searched in turn on the calling thread.
+#[derive(Clone, Copy, Debug)]
+pub struct SingleThread;
+
+/// A thread spawned per file, at most `--threads` at once.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadPerFile;
+
+/// Files handed to a pool of `workers` threads.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadPool {
+    pub workers: usize,
+}
+
+/// Files searched in parallel on a rayon pool of `--threads`, bridged from the sequential walk.
+#[derive(Clone, Copy, Debug)]
+pub struct Rayon;
+
+/// The walk streamed to Tokio tasks, `--threads` searched at once.
+#[derive(Clone, Copy, Debug)]
+pub struct Async;
+
+impl SearchStrategy for SingleThread {
+    fn name(&self) -> String {
+        "single_thread_read_files".to_string()
+    }
+
+    fn search<P: Printable>(
+        &self,
+        search: Search,
+        print_behaviour: P,
+    ) -> impl Future<Output = Result<Outcome, MyErrors>> {
+        blocking(move || crate::use_single_thread(search, print_behaviour))
+    }
+}
+
+impl SearchStrategy for ThreadPerFile {
+    fn name(&self) -> String {
+        "thread_per_file_read_files".to_string()
+    }
+
+    fn search<P: Printable>(
+        &self,
+        search: Search,
+        print_behaviour: P,
+    ) -> impl Future<Output = Result<Outcome, MyErrors>> {
+        blocking(move || crate::use_thread_per_file(search, print_behaviour))
+    }
+}
+
+impl SearchStrategy for ThreadPool {
+    fn name(&self) -> String {
+        if self.workers == 1 {
+            "use_thread_pool_single_thread".to_string()
+        } else {
+            format!("use_thread_pool_multiple_{}", self.workers)
+        }
+    }
+
+    fn search<P: Printable>(
+        &self,
+        search: Search,
+        print_behaviour: P,
+    ) -> impl Future<Output = Result<Outcome, MyErrors>> {
+        let search = search.with_threads(self.workers);
+        blocking(move || crate::use_thread_pool(search, print_behaviour))
+    }
+}
+
+impl SearchStrategy for Rayon {
+    fn name(&self) -> String {
+        "rayon_read_files".to_string()
+    }
+
+    fn search<P: Printable>(
+        &self,
+        search: Search,
+        print_behaviour: P,
+    ) -> impl Future<Output = Result<Outcome, MyErrors>> {
+        blocking(move || crate::use_rayon(search, print_behaviour))
+    }
+}
+
+impl SearchStrategy for Async {
+    fn name(&self) -> String {
+        "async_read_files".to_string()
+    }
+
+    fn search<P: Printable>(
+        &self,
+        search: Search,
+        print_behaviour: P,
+    ) -> impl Future<Output = Result<Outcome, MyErrors>> {
+        crate::use_async_two(search, print_behaviour)
+    }
+}
+
+/// Runs a strategy which blocks until it's done on Tokio's blocking threads.
+async fn blocking<F>(search: F) -> Result<Outcome, MyErrors>
+where
+    F: FnOnce() -> Result<Outcome, MyErrors> + Send + 'static,
+{
+    tokio::task::spawn_blocking(search)
+        .await
+        .map_err(MyErrors::TokioError)?
+}
+
+/// Each registered strategy, dispatched to its `SearchStrategy` implementation.
+#[derive(Clone, Copy, Debug)]
+pub enum Strategy {
+    SingleThread(SingleThread),
+    ThreadPerFile(ThreadPerFile),
+    ThreadPool(ThreadPool),
+    Rayon(Rayon),
+    Async(Async),
+}
+
+impl Strategy {
+    /// Every strategy in the order the CLI runs them, the threadpool both with a single worker and
+    /// `threads` (unless that's also one, which would only run the same search twice).
+    pub fn registered(threads: usize) -> Vec<Strategy> {
+        let mut registered = vec![
+            Strategy::SingleThread(SingleThread),
+            Strategy::ThreadPerFile(ThreadPerFile),
+            Strategy::ThreadPool(ThreadPool { workers: 1 }),
+        ];
+        if threads > 1 {
+            registered.push(Strategy::ThreadPool(ThreadPool { workers: threads }));
+        }
+        registered.extend([Strategy::Rayon(Rayon), Strategy::Async(Async)]);
+        registered
+    }
+
+    /// Sets up the search from the CLI then runs it, as the `*_read_files` functions do.
+    pub async fn run<P: Printable>(
+        &self,
+        args: &Cli,
+        print_behaviour: P,
+        cancel: CancellationToken,
+    ) -> Result<Outcome, MyErrors> {
+        self.search(Search::from_cli(args, cancel)?, print_behaviour)
+            .await
+    }
+}
+
+impl SearchStrategy for Strategy {
+    fn name(&self) -> String {
+        match self {
+            Strategy::SingleThread(strategy) => strategy.name(),
+            Strategy::ThreadPerFile(strategy) => strategy.name(),
+            Strategy::ThreadPool(strategy) => strategy.name(),
+            Strategy::Rayon(strategy) => strategy.name(),
+            Strategy::Async(strategy) => strategy.name(),
+        }
+    }
+
+    async fn search<P: Printable>(
+        &self,
+        search: Search,
+        print_behaviour: P,
+    ) -> Result<Outcome, MyErrors> {
+        match self {
+            Strategy::SingleThread(strategy) => strategy.search(search, print_behaviour).await,
+            Strategy::ThreadPerFile(strategy) => strategy.search(search, print_behaviour).await,
+            Strategy::ThreadPool(strategy) => strategy.search(search, print_behaviour).await,
+            Strategy::Rayon(strategy) => strategy.search(search, print_behaviour).await,
+            Strategy::Async(strategy) => strategy.search(search, print_behaviour).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{SearchStrategy, Strategy};
+    use crate::cancel::CancellationToken;
+    use crate::{Cli, PrintDisable};
+
+    #[tokio::test]
+    async fn test_registered_strategies_agree() {
+        let registered = Strategy::registered(4);
+        let names: Vec<String> = registered.iter().map(SearchStrategy::name).collect();
+        assert_eq!(
+            names,
+            [
+                "single_thread_read_files",
+                "thread_per_file_read_files",
+                "use_thread_pool_single_thread",
+                "use_thread_pool_multiple_4",
+                "rayon_read_files",
+                "async_read_files",
+            ]
+        );
+        // Names are benchmark IDs, so have to be unique
+        assert_eq!(Strategy::registered(1).len(), registered.len() - 1);
+
+        let cli = Cli::parse_from(["rustcomb", "test_files", "metus mus. Elit convallis"]);
+        for strategy in &registered {
+            let outcome = strategy
+                .run(&cli, PrintDisable, CancellationToken::new())
+                .await
+                .unwrap();
+            assert!(outcome.matched, "{} found nothing", strategy.name());
+            assert_eq!(outcome.errors, 0, "{} reported errors", strategy.name());
+        }
+
+        let invalid = Cli::parse_from([
+            "rustcomb",
+            "test_files",
+            "(",
+            "--file-pattern-regex",
+            "regex",
+        ]);
+        for strategy in &registered {
+            assert!(
+                strategy
+                    .run(&invalid, PrintDisable, CancellationToken::new())
+                    .await
+                    .is_err(),
+                "{} accepted an invalid pattern",
+                strategy.name()
+            );
+        }
+    }
+}
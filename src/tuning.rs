@@ -0,0 +1,81 @@
+use crate::{Cli, get_cpuworkers};
+
+/// Results queued between the searching threads and the printer before searching blocks.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1000;
+
+/**
+ * How much the parallel strategies do at once, set at runtime rather than compiled in.
+ *
+ * `threads` caps the threads of thread per file, the threadpool's and rayon's workers and the files
+ * searched at once by async. The defaults suit a desktop, large machines (e.g. build servers with
+ * dozens of cores) usually want more threads and a larger channel so the printer never starves them.
+ * How much output is buffered is part of `Output`, as the single threaded strategy prints too.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tuning {
+    pub threads: usize,
+    pub channel_capacity: usize,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            threads: get_cpuworkers(),
+            channel_capacity: DEFAULT_CHANNEL_CAPACITY,
+        }
+    }
+}
+
+impl Tuning {
+    pub fn from_cli(args: &Cli) -> Self {
+        Tuning {
+            threads: args.threads.unwrap_or_else(get_cpuworkers),
+            channel_capacity: args.channel_capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{DEFAULT_CHANNEL_CAPACITY, Tuning};
+    use crate::Cli;
+    use crate::output::Output;
+
+    #[test]
+    fn test_from_cli() {
+        let cli = Cli::parse_from(["rustcomb", "test_files", "needle"]);
+        assert_eq!(
+            Tuning::from_cli(&cli).channel_capacity,
+            DEFAULT_CHANNEL_CAPACITY
+        );
+        assert_eq!(Tuning::from_cli(&cli).threads, crate::get_cpuworkers());
+
+        let cli = Cli::parse_from([
+            "rustcomb",
+            "test_files",
+            "needle",
+            "-j",
+            "96",
+            "--channel-capacity",
+            "10000",
+            "--output-buffer",
+            "1048576",
+            "--flush-threshold",
+            "0",
+        ]);
+        assert_eq!(
+            Tuning::from_cli(&cli),
+            Tuning {
+                threads: 96,
+                channel_capacity: 10000,
+            }
+        );
+        let output = Output::from_cli(&cli);
+        assert_eq!(output.buffer_capacity, 1024 * 1024);
+        assert_eq!(output.flush_threshold, 0);
+
+        assert!(Cli::try_parse_from(["rustcomb", "test_files", "needle", "-j", "0"]).is_err());
+    }
+}
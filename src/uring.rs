@@ -0,0 +1,412 @@
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use futures::stream::{self, Stream, StreamExt};
+use io_uring::{IoUring, opcode, squeue, types};
+
+use crate::{FileInfo, MyErrors, Operation};
+
+/// Files opened and read at once, also the size of the submission queue.
+pub const BATCH_SIZE: usize = 64;
+/// Read from each file per round trip, most source files are read in one (and a second finds the end).
+const READ_LEN: usize = 64 * 1024;
+
+thread_local! {
+    /// One ring per blocking thread, `None` when the kernel (or a seccomp filter) doesn't allow io_uring.
+    static READER: RefCell<Option<UringReader>> =
+        RefCell::new(UringReader::new(BATCH_SIZE as u32).ok());
+}
+
+/**
+ * Reads whole files with io_uring, a batch at a time.
+ *
+ * Each step (opening, every read and closing) is submitted for the whole batch at once, so
+ * reading a batch of small files costs a handful of system calls rather than several per file.
+ */
+pub struct UringReader {
+    ring: IoUring,
+    /// Set once the ring has failed, after which it's never entered again
+    broken: bool,
+}
+
+/// A file being read: its descriptor, what's been read so far and whether it's reached the end.
+struct Reading {
+    fd: i32,
+    contents: Vec<u8>,
+    done: bool,
+}
+
+impl UringReader {
+    pub fn new(entries: u32) -> io::Result<Self> {
+        Ok(UringReader {
+            ring: IoUring::new(entries)?,
+            broken: false,
+        })
+    }
+
+    /// Whether the ring has failed, every file read since has failed without touching it.
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// The contents of each path, in the same order. Failing to read one file doesn't affect the others.
+    pub fn read_files(&mut self, paths: &[&Path]) -> Vec<Result<Vec<u8>, MyErrors>> {
+        let batch_size = self.ring.params().sq_entries() as usize;
+        paths
+            .chunks(batch_size)
+            .flat_map(|batch| self.read_batch(batch))
+            .collect()
+    }
+
+    fn read_batch(&mut self, paths: &[&Path]) -> Vec<Result<Vec<u8>, MyErrors>> {
+        if self.broken {
+            return paths
+                .iter()
+                .map(|path| {
+                    let err = io::Error::other("io_uring failed reading an earlier batch");
+                    Err(MyErrors::io(path, Operation::Open, err))
+                })
+                .collect();
+        }
+
+        // Only a path containing a NUL byte has no C string, and it couldn't be opened anyway
+        let names: Vec<Option<CString>> = paths
+            .iter()
+            .map(|path| CString::new(path.as_os_str().as_bytes()).ok())
+            .collect();
+        let mut files: Vec<Result<Reading, MyErrors>> = names
+            .iter()
+            .zip(paths)
+            .map(|(name, path)| match name {
+                Some(_) => Ok(Reading::default()),
+                None => Err(MyErrors::io(
+                    path,
+                    Operation::Open,
+                    io::ErrorKind::InvalidFilename.into(),
+                )),
+            })
+            .collect();
+
+        let opens: Vec<squeue::Entry> = names
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| Some((i, name.as_ref()?)))
+            .map(|(i, name)| {
+                opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), name.as_ptr())
+                    .flags(libc::O_RDONLY | libc::O_CLOEXEC)
+                    .build()
+                    .user_data(i as u64)
+            })
+            .collect();
+        self.complete(&opens, &mut files, Operation::Open, paths, |file, fd| {
+            file.fd = fd;
+        });
+        // The opens pointed to `names`, so it has to outlive them
+        drop(opens);
+        drop(names);
+
+        while files
+            .iter()
+            .any(|file| matches!(file, Ok(file) if !file.done))
+        {
+            let reads: Vec<squeue::Entry> = files
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(i, file)| match file {
+                    Ok(file) if !file.done => Some((i, file)),
+                    _ => None,
+                })
+                .map(|(i, file)| {
+                    // Growing for every read would reallocate (and copy) each file just to find its end
+                    if file.contents.capacity() - file.contents.len() < READ_LEN / 2 {
+                        file.contents.reserve(READ_LEN);
+                    }
+                    let len = file.contents.len();
+                    let spare = (file.contents.capacity() - len).min(u32::MAX as usize);
+                    // SAFETY: `spare` bytes are reserved after `len`, and `contents` isn't touched
+                    // until the read completes
+                    let buf = unsafe { file.contents.as_mut_ptr().add(len) };
+                    opcode::Read::new(types::Fd(file.fd), buf, spare as u32)
+                        .offset(len as u64)
+                        .build()
+                        .user_data(i as u64)
+                })
+                .collect();
+            self.complete(&reads, &mut files, Operation::Read, paths, |file, read| {
+                if read == 0 {
+                    file.done = true;
+                } else {
+                    // SAFETY: the kernel has written `read` bytes after the existing contents
+                    unsafe { file.contents.set_len(file.contents.len() + read as usize) };
+                }
+            });
+        }
+
+        let mut open: Vec<Option<i32>> = files
+            .iter()
+            .map(|file| file.as_ref().ok().map(|file| file.fd))
+            .collect();
+        let closes: Vec<squeue::Entry> = open
+            .iter()
+            .enumerate()
+            .filter_map(|(i, fd)| Some((i, (*fd)?)))
+            .map(|(i, fd)| {
+                opcode::Close::new(types::Fd(fd))
+                    .build()
+                    .user_data(i as u64)
+            })
+            .collect();
+        // Nothing useful can be done about a failed close of a file only read from, but a close
+        // the ring never ran (as it failed first) is done directly rather than leaking the file
+        let (closed, _) = self.submit(&closes);
+        for (i, _) in closed {
+            open[i] = None;
+        }
+        open.into_iter().flatten().for_each(close);
+
+        files
+            .into_iter()
+            .map(|file| file.map(|file| file.contents))
+            .collect()
+    }
+
+    /// Submits an operation for each file, calling `f` with each successful result.
+    /// A file whose operation fails is replaced with the error (and closed if it was open), as is
+    /// every file still open if the ring does.
+    fn complete(
+        &mut self,
+        entries: &[squeue::Entry],
+        files: &mut [Result<Reading, MyErrors>],
+        operation: Operation,
+        paths: &[&Path],
+        mut f: impl FnMut(&mut Reading, i32),
+    ) {
+        // Even after the ring fails, whatever did complete is applied so opened files are closed
+        let (completed, result) = self.submit(entries);
+        for (i, result) in completed {
+            if result < 0 {
+                let err = io::Error::from_raw_os_error(-result);
+                // Opened but failed to read, so still has to be closed
+                if let Ok(file) = &files[i]
+                    && operation != Operation::Open
+                {
+                    close(file.fd);
+                }
+                files[i] = Err(MyErrors::io(paths[i], operation, err));
+            } else if let Ok(file) = &mut files[i] {
+                f(file, result);
+            }
+        }
+
+        if let Err(err) = result {
+            for (file, path) in files.iter_mut().zip(paths) {
+                if let Ok(reading) = file {
+                    close(reading.fd);
+                    let err = io::Error::new(err.kind(), err.to_string());
+                    *file = Err(MyErrors::io(path, operation, err));
+                }
+            }
+        }
+    }
+
+    /**
+     * Submits every entry and waits for them all, returning each entry's `user_data` and result.
+     *
+     * An interrupted wait is retried. Any other failure breaks the ring, it's never entered again
+     * so entries still queued are never run. Those the kernel already took are waited for before
+     * returning, so nothing they read into is freed while in use and none of their completions
+     * are left for the next call.
+     */
+    fn submit(&mut self, entries: &[squeue::Entry]) -> (Vec<(usize, i32)>, io::Result<()>) {
+        if self.broken {
+            return (Vec::new(), Err(io::Error::other("io_uring failed earlier")));
+        }
+
+        let mut completed = Vec::with_capacity(entries.len());
+        let mut pushed = 0;
+        for entry in entries {
+            // SAFETY: every buffer and path referenced by an entry outlives this call, which
+            // doesn't return until each entry the kernel has taken has completed
+            if unsafe { self.ring.submission().push(entry) }.is_err() {
+                break;
+            }
+            pushed += 1;
+        }
+        let mut result = if pushed < entries.len() {
+            Err(io::Error::other("io_uring submission queue is full"))
+        } else {
+            Ok(())
+        };
+
+        while result.is_ok() && completed.len() < pushed {
+            match self.ring.submit_and_wait(pushed - completed.len()) {
+                Err(err) if err.kind() != io::ErrorKind::Interrupted => result = Err(err),
+                _ => {}
+            }
+            completed.extend(
+                self.ring
+                    .completion()
+                    .map(|cqe| (cqe.user_data() as usize, cqe.result())),
+            );
+        }
+
+        if result.is_err() {
+            self.broken = true;
+            // Polled rather than waited for, as entering the ring would also submit the rest
+            let taken = pushed - self.ring.submission().len();
+            loop {
+                completed.extend(
+                    self.ring
+                        .completion()
+                        .map(|cqe| (cqe.user_data() as usize, cqe.result())),
+                );
+                if completed.len() >= taken {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        (completed, result)
+    }
+}
+
+/// Closes a file opened by the ring, if it was.
+fn close(fd: i32) {
+    if fd >= 0 {
+        // SAFETY: only descriptors the ring opened for this reader are closed, and only once
+        unsafe { libc::close(fd) };
+    }
+}
+
+impl Default for Reading {
+    fn default() -> Self {
+        Reading {
+            fd: -1,
+            contents: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+/**
+ * Reads ahead of the async strategy's search, a batch of files at a time on a blocking thread.
+ *
+ * Archive members and standard input already have their contents. Files which fail to read are
+ * passed through unread, as are all files without io_uring (e.g. blocked within a container), to
+ * be read (and any error reported) as usual.
+ */
+pub(crate) fn read_ahead<S>(files: S) -> impl Stream<Item = Result<FileInfo, MyErrors>>
+where
+    S: Stream<Item = Result<FileInfo, MyErrors>>,
+{
+    files
+        .ready_chunks(BATCH_SIZE)
+        .then(|batch| tokio::task::spawn_blocking(move || read_batch(batch)))
+        .flat_map(|read| {
+            stream::iter(read.unwrap_or_else(|err| vec![Err(MyErrors::TokioError(err))]))
+        })
+}
+
+fn read_batch(mut batch: Vec<Result<FileInfo, MyErrors>>) -> Vec<Result<FileInfo, MyErrors>> {
+    READER.with_borrow_mut(|slot| {
+        let Some(reader) = slot else {
+            return batch;
+        };
+
+        let unread: Vec<usize> = batch
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| matches!(file, Ok(file) if file.contents.is_none()))
+            .map(|(i, _)| i)
+            .collect();
+        let paths: Vec<&Path> = unread
+            .iter()
+            .filter_map(|&i| batch[i].as_ref().ok())
+            .map(|file| file.path.as_path())
+            .collect();
+        let read = reader.read_files(&paths);
+
+        for (i, contents) in unread.into_iter().zip(read) {
+            if let (Ok(file), Ok(contents)) = (&mut batch[i], contents) {
+                file.contents = Some(contents);
+            }
+        }
+        // Later batches on this thread are read as usual
+        if reader.is_broken() {
+            *slot = None;
+        }
+        batch
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use assert_fs::TempDir;
+    use io_uring::opcode;
+
+    use super::{BATCH_SIZE, READ_LEN, UringReader};
+    use crate::Operation;
+
+    #[test]
+    fn test_read_files() {
+        let Ok(mut reader) = UringReader::new(BATCH_SIZE as u32) else {
+            eprintln!("io_uring is unavailable, skipping");
+            return;
+        };
+        let dir = TempDir::new().unwrap();
+
+        let small = dir.path().join("small.txt");
+        std::fs::write(&small, "needle\n").unwrap();
+        let empty = dir.path().join("empty.txt");
+        std::fs::write(&empty, "").unwrap();
+        // Takes several rounds of reads
+        let large = dir.path().join("large.txt");
+        let large_contents: Vec<u8> = (0..READ_LEN * 3 + 17).map(|i| i as u8).collect();
+        std::fs::write(&large, &large_contents).unwrap();
+        let missing = dir.path().join("missing.txt");
+
+        let mut paths: Vec<&Path> = vec![&small, &missing, &empty, &large];
+        // More than a single batch
+        paths.extend(std::iter::repeat_n(small.as_path(), BATCH_SIZE));
+
+        let read = reader.read_files(&paths);
+        assert_eq!(read.len(), paths.len());
+        assert_eq!(read[0].as_ref().unwrap(), b"needle\n");
+        let err = read[1].as_ref().unwrap_err();
+        assert_eq!(err.operation(), Some(Operation::Open));
+        assert_eq!(err.path(), Some(missing.as_path()));
+        assert!(read[2].as_ref().unwrap().is_empty());
+        assert_eq!(read[3].as_ref().unwrap(), &large_contents);
+        assert!(read[4..].iter().all(|r| r.as_ref().unwrap() == b"needle\n"));
+    }
+
+    #[test]
+    fn test_failed_ring_is_not_used_again() {
+        let Ok(mut reader) = UringReader::new(4) else {
+            eprintln!("io_uring is unavailable, skipping");
+            return;
+        };
+        let dir = TempDir::new().unwrap();
+        let small = dir.path().join("small.txt");
+        std::fs::write(&small, "needle\n").unwrap();
+
+        // More entries than the queue holds, the first four are queued but never submitted
+        let nops: Vec<_> = (0..6)
+            .map(|i| opcode::Nop::new().build().user_data(i))
+            .collect();
+        let (completed, result) = reader.submit(&nops);
+        assert!(result.is_err());
+        assert!(completed.is_empty());
+        assert!(reader.is_broken());
+
+        let read = reader.read_files(&[small.as_path()]);
+        assert_eq!(read[0].as_ref().unwrap_err().path(), Some(small.as_path()));
+    }
+}
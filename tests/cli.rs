@@ -0,0 +1,383 @@
+//! Runs the `rustcomb` binary against small trees, snapshotting its exit code, stdout and stderr.
+//! Review changed snapshots with `cargo insta review`, or accept them all with `INSTA_UPDATE=always`.
+
+use std::io::Write;
+use std::process::Output;
+use std::time::{Duration, SystemTime};
+
+use assert_cmd::Command;
+use assert_fs::TempDir;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+/// Matches in plain and CRLF files, a file without and one that isn't valid UTF-8.
+fn tree() -> TempDir {
+    let dir = TempDir::new().unwrap();
+    dir.child("a.txt").write_str("one needle\nhay\n").unwrap();
+    dir.child("sub/b.log").write_str("needle two\r\n").unwrap();
+    dir.child("sub/c.txt").write_str("nothing here\n").unwrap();
+    dir.child("latin1.txt")
+        .write_binary(b"caf\xe9 needle\n")
+        .unwrap();
+    dir
+}
+
+/// Run from within `dir` (so paths are relative) with deterministic output: sorted, two threads
+/// and no colour unless the test asks for it.
+fn rustcomb(dir: &TempDir) -> Command {
+    let mut cmd = unsorted(dir);
+    cmd.args(["--sort", "path"]);
+    cmd
+}
+
+/// For the tests choosing their own order, as `--sort` can only be given once.
+fn unsorted(dir: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("rustcomb").unwrap();
+    cmd.current_dir(dir.path()).args(["-j", "2"]);
+    cmd
+}
+
+/// Every line of stdout, sorted, for output whose order isn't deterministic.
+fn sorted_lines(output: &Output) -> Vec<String> {
+    let mut lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect();
+    lines.sort();
+    lines
+}
+
+/// Every strategy's results are in the snapshot, timings and the echoed arguments aren't.
+fn snapshot(name: &str, output: &Output) {
+    let rendered = format!(
+        "exit: {:?}\n--- stdout\n{}--- stderr\n{}",
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    );
+
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(r"Args: Cli \{.*\}", "Args: [args]");
+    settings.add_filter(r"\d+(\.\d+)?(ns|µs|ms|s)\b", "[elapsed]");
+    settings.add_filter(
+        r#""secs":\d+,"nanos":\d+,"human":"[^"]*""#,
+        r#""secs":[secs],"nanos":[nanos],"human":"[elapsed]""#,
+    );
+    // Includes the elapsed times written in each `end` message
+    settings.add_filter(r#""bytes_printed":\d+"#, r#""bytes_printed":[bytes]"#);
+    settings.bind(|| insta::assert_snapshot!(name, rendered));
+}
+
+fn run(name: &str, cmd: &mut Command) -> Output {
+    let output = cmd.output().unwrap();
+    snapshot(name, &output);
+    output
+}
+
+#[test]
+fn test_matches() {
+    let dir = tree();
+    let output = run(
+        "matches",
+        rustcomb(&dir).args([".", "needle", "--color", "never"]),
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_no_match_exits_1() {
+    let dir = tree();
+    let output = run(
+        "no_match",
+        rustcomb(&dir).args([".", "absent", "--color", "never"]),
+    );
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_path_pattern() {
+    let dir = tree();
+    run(
+        "path_pattern",
+        rustcomb(&dir).args([".", "needle", ".txt", "--color", "never"]),
+    );
+}
+
+#[test]
+fn test_path_pattern_regex() {
+    let dir = tree();
+    run(
+        "path_pattern_regex",
+        rustcomb(&dir).args([".", "needle", "-p", "regex", r"^(a|b)\.", "--vimgrep"]),
+    );
+}
+
+#[test]
+fn test_regex() {
+    let dir = tree();
+    run(
+        "regex",
+        rustcomb(&dir).args([
+            ".",
+            "-f",
+            "regex",
+            r"^\w+ (needle|here)$",
+            "--color",
+            "never",
+        ]),
+    );
+}
+
+#[test]
+fn test_invalid_regex_exits_2() {
+    let dir = tree();
+    rustcomb(&dir)
+        .args([".", "-f", "regex", "("])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::starts_with("Error: "))
+        .stdout(predicate::str::contains("needle").not());
+}
+
+#[test]
+fn test_invalid_args_exit_2() {
+    let dir = tree();
+    rustcomb(&dir)
+        .args([".", "needle", "-j", "0"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("--threads"));
+    rustcomb(&dir)
+        .args([".", "needle", "--json", "--vimgrep"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_sortr() {
+    let dir = tree();
+    // `--sortr` conflicts with the `--sort` every other test passes
+    run(
+        "sortr",
+        unsorted(&dir).args([".", "needle", "--sortr", "path", "--color", "never"]),
+    );
+}
+
+#[test]
+fn test_sort_modified() {
+    let dir = tree();
+    // Newest last, the opposite of the path order
+    let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    for (age, name) in ["sub/b.log", "latin1.txt", "a.txt"].iter().enumerate() {
+        std::fs::File::options()
+            .write(true)
+            .open(dir.child(name).path())
+            .unwrap()
+            .set_modified(epoch + Duration::from_secs(age as u64 * 60))
+            .unwrap();
+    }
+    run(
+        "sort_modified",
+        unsorted(&dir).args([".", "needle", "--sort", "modified", "--vimgrep"]),
+    );
+}
+
+#[test]
+fn test_sort_accessed_and_created() {
+    let dir = tree();
+    // Neither timestamp can be set, or is always supported, so only the lines found are compared
+    let by_path = rustcomb(&dir)
+        .args([".", "needle", "--vimgrep"])
+        .output()
+        .unwrap();
+    for by in ["accessed", "created"] {
+        let output = unsorted(&dir)
+            .args([".", "needle", "--vimgrep", "--sort", by])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "--sort {by}");
+        assert_eq!(sorted_lines(&output), sorted_lines(&by_path), "--sort {by}");
+    }
+}
+
+#[test]
+fn test_json() {
+    let dir = tree();
+    let output = run("json", rustcomb(&dir).args([".", "needle", "--json"]));
+    // Only results on stdout, so it can be piped into a JSON Lines reader
+    for line in String::from_utf8(output.stdout).unwrap().lines() {
+        serde_json::from_str::<serde_json::Value>(line).unwrap();
+    }
+}
+
+#[test]
+fn test_vimgrep() {
+    let dir = tree();
+    run("vimgrep", rustcomb(&dir).args([".", "needle", "--vimgrep"]));
+}
+
+#[test]
+fn test_emacs() {
+    let dir = tree();
+    run("emacs", rustcomb(&dir).args([".", "needle", "--emacs"]));
+}
+
+#[test]
+fn test_colours() {
+    let dir = tree();
+    run(
+        "colours",
+        rustcomb(&dir).args([
+            ".",
+            "two",
+            "--color",
+            "always",
+            "--colors",
+            "match:fg:yellow",
+            "--colors",
+            "path:style:bold",
+        ]),
+    );
+}
+
+#[test]
+fn test_stats() {
+    let dir = tree();
+    run(
+        "stats",
+        rustcomb(&dir).args([".", "needle", "--stats", "--color", "never"]),
+    );
+}
+
+#[test]
+fn test_stats_json() {
+    let dir = tree();
+    run(
+        "stats_json",
+        rustcomb(&dir).args([".", "needle", "--stats", "--json"]),
+    );
+}
+
+#[test]
+fn test_mmap_never() {
+    let dir = tree();
+    let mapped = rustcomb(&dir)
+        .args([".", "needle", "--vimgrep"])
+        .output()
+        .unwrap();
+    let buffered = rustcomb(&dir)
+        .args([".", "needle", "--vimgrep", "--mmap", "never"])
+        .output()
+        .unwrap();
+    assert_eq!(buffered.status.code(), Some(0));
+    assert_eq!(buffered.stdout, mapped.stdout);
+}
+
+#[test]
+fn test_tuning_leaves_output_unchanged() {
+    let dir = tree();
+    let default = rustcomb(&dir)
+        .args([".", "needle", "--vimgrep"])
+        .output()
+        .unwrap();
+    for tuning in [
+        ["--channel-capacity", "1"],
+        ["--output-buffer", "1"],
+        ["--flush-threshold", "0"],
+    ] {
+        let tuned = rustcomb(&dir)
+            .args([".", "needle", "--vimgrep"])
+            .args(tuning)
+            .output()
+            .unwrap();
+        assert_eq!(tuned.status.code(), Some(0), "{tuning:?}");
+        assert_eq!(tuned.stdout, default.stdout, "{tuning:?}");
+    }
+}
+
+#[test]
+fn test_stdin() {
+    let dir = tree();
+    run(
+        "stdin",
+        rustcomb(&dir)
+            .args(["-", "needle", "--vimgrep"])
+            .write_stdin("hay\nneedle from stdin\n"),
+    );
+}
+
+#[test]
+fn test_files_from() {
+    let dir = tree();
+    dir.child("list.txt")
+        .write_str("a.txt\nsub/b.log\n")
+        .unwrap();
+    run(
+        "files_from",
+        rustcomb(&dir).args([".", "needle", "--files-from", "list.txt", "--vimgrep"]),
+    );
+}
+
+#[test]
+fn test_files_from_null_data() {
+    let dir = tree();
+    // A name containing a newline can only be listed NUL separated
+    dir.child("new\nline.txt").write_str("needle\n").unwrap();
+    dir.child("list")
+        .write_binary(b"new\nline.txt\0sub/b.log\0")
+        .unwrap();
+    run(
+        "files_from_null_data",
+        rustcomb(&dir).args([
+            ".",
+            "needle",
+            "--files-from",
+            "list",
+            "--null-data",
+            "--vimgrep",
+        ]),
+    );
+}
+
+#[test]
+fn test_missing_file_exits_2() {
+    let dir = tree();
+    dir.child("list.txt")
+        .write_str("a.txt\nmissing.txt\n")
+        .unwrap();
+    let output = run(
+        "missing_file",
+        rustcomb(&dir).args([".", "needle", "--files-from", "list.txt", "--vimgrep"]),
+    );
+    assert_eq!(output.status.code(), Some(2), "An error, even with a match");
+
+    // Still fails, just quietly
+    let quiet = rustcomb(&dir)
+        .args([".", "needle", "--files-from", "list.txt", "--vimgrep", "-s"])
+        .output()
+        .unwrap();
+    assert_eq!(quiet.status.code(), Some(2));
+    assert!(!String::from_utf8_lossy(&quiet.stderr).contains("missing.txt"));
+}
+
+#[test]
+fn test_search_archives() {
+    let dir = tree();
+    let archive = std::fs::File::create(dir.child("archive.zip").path()).unwrap();
+    let mut zip = zip::ZipWriter::new(archive);
+    zip.start_file("inner.txt", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    zip.write_all(b"zipped needle\n").unwrap();
+    zip.finish().unwrap();
+
+    run(
+        "search_archives",
+        rustcomb(&dir).args([".", "zipped", "-z", "--vimgrep"]),
+    );
+    // Without `-z` the archive is searched as is, and compressed
+    rustcomb(&dir)
+        .args([".", "zipped", "--vimgrep"])
+        .assert()
+        .code(1);
+}
@@ -0,0 +1,272 @@
+//! Every strategy searches randomly generated trees with random patterns, and must find the same
+//! matches and errors. A failure names the seed, `EQUIVALENCE_SEED=<seed>` reruns just that tree.
+//!
+//! Trees sometimes include archives (searched with `-z`) and are sometimes read with `--mmap never`.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use assert_fs::TempDir;
+use clap::Parser;
+use fastrand::Rng;
+
+use rustcomb::cancel::CancellationToken;
+use rustcomb::stats::Outcome;
+use rustcomb::strategy::{SearchStrategy, Strategy};
+use rustcomb::{Cli, FileMatches, MyErrors, Printable};
+
+const SEEDS: u64 = 24;
+/// Seeds with a file large enough to be split into chunks, fewer as each is slow to search
+const LARGE_SEEDS: u64 = 2;
+/// Over the library's `MIN_CHUNK_LEN`, below which a file is searched in one piece
+const LARGE_LEN: usize = 5 * 1024 * 1024;
+const WORDS: &[&str] = &[
+    "needle", "Needle", "NEEDLE", "hay", "haystack", "lorem", "ipsum", "café", "naïve", "über",
+    "a", "aa", "b", "", " ",
+];
+
+/// Keeps each file's matches rather than printing them, shared with the strategy's printer.
+#[derive(Clone, Default)]
+struct Collect(Arc<Mutex<Vec<FileMatches>>>);
+
+impl Collect {
+    /// Sorted by path, as only the parallel strategies' order differs.
+    fn take(&self) -> Vec<FileMatches> {
+        let mut found = std::mem::take(&mut *self.0.lock().unwrap());
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        found
+    }
+}
+
+impl Printable for Collect {
+    fn writeln_w_handler<T, F>(&self, _: &mut BufWriter<T>, _: F) -> Result<(), MyErrors>
+    where
+        T: Write,
+        F: FnOnce(&mut BufWriter<T>) -> Result<(), MyErrors>,
+    {
+        Ok(())
+    }
+
+    fn information_out<T, F>(
+        &self,
+        _: &mut BufWriter<T>,
+        data: FileMatches,
+        _: F,
+    ) -> Result<(), MyErrors>
+    where
+        T: Write,
+        F: FnOnce(&mut BufWriter<T>, FileMatches) -> Result<(), MyErrors>,
+    {
+        self.0.lock().unwrap().push(data);
+        Ok(())
+    }
+}
+
+/// A line of words, occasionally with a byte sequence that isn't valid UTF-8.
+fn random_line(rng: &mut Rng) -> Vec<u8> {
+    let mut line = Vec::new();
+    for i in 0..rng.usize(0..8) {
+        if i > 0 {
+            line.push(b' ');
+        }
+        if rng.u8(..) < 8 {
+            line.extend_from_slice(&[0xff, 0xc3]);
+        }
+        line.extend_from_slice(rng.choice(WORDS).unwrap().as_bytes());
+    }
+    line
+}
+
+fn random_contents(rng: &mut Rng) -> Vec<u8> {
+    let newline: &[u8] = if rng.bool() { b"\n" } else { b"\r\n" };
+    let mut contents = Vec::new();
+    for _ in 0..rng.usize(0..40) {
+        contents.extend(random_line(rng));
+        contents.extend_from_slice(newline);
+    }
+    // Sometimes without a final line terminator
+    if rng.bool() {
+        contents.extend(random_line(rng));
+    }
+    contents
+}
+
+/// Nested directories of files, some empty.
+fn random_tree(rng: &mut Rng, dir: &Path, depth: usize) {
+    for i in 0..rng.usize(1..6) {
+        let extension = rng.choice(["txt", "log", "rs"]).unwrap();
+        let contents = if rng.u8(..) < 16 {
+            Vec::new()
+        } else {
+            random_contents(rng)
+        };
+        std::fs::write(dir.join(format!("{i}.{extension}")), contents).unwrap();
+    }
+    if depth < 3 {
+        for i in 0..rng.usize(0..3) {
+            let sub = dir.join(format!("dir{i}"));
+            std::fs::create_dir(&sub).unwrap();
+            random_tree(rng, &sub, depth + 1);
+        }
+    }
+}
+
+/// Lines of random contents until there's at least `len` bytes.
+fn large_contents(rng: &mut Rng, len: usize) -> Vec<u8> {
+    let mut contents = Vec::with_capacity(len);
+    while contents.len() < len {
+        contents.extend(random_line(rng));
+        contents.push(b'\n');
+    }
+    contents
+}
+
+/// A zip or tar (possibly gzipped) of random files, some in directories.
+fn random_archive(rng: &mut Rng, dir: &Path) {
+    let members: Vec<(String, Vec<u8>)> = (0..rng.usize(1..5))
+        .map(|i| {
+            let name = if rng.bool() {
+                format!("inner{i}.txt")
+            } else {
+                format!("nested/inner{i}.log")
+            };
+            (name, random_contents(rng))
+        })
+        .collect();
+
+    match rng.usize(0..3) {
+        0 => {
+            let mut zip = zip::ZipWriter::new(File::create(dir.join("archive.zip")).unwrap());
+            for (name, contents) in &members {
+                zip.start_file(name.as_str(), zip::write::SimpleFileOptions::default())
+                    .unwrap();
+                zip.write_all(contents).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+        kind => {
+            let file = File::create(dir.join(if kind == 1 {
+                "archive.tar"
+            } else {
+                "archive.tar.gz"
+            }))
+            .unwrap();
+            let writer: Box<dyn Write> = if kind == 1 {
+                Box::new(file)
+            } else {
+                Box::new(flate2::write::GzEncoder::new(
+                    file,
+                    flate2::Compression::default(),
+                ))
+            };
+            let mut tar = tar::Builder::new(writer);
+            for (name, contents) in &members {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                tar.append_data(&mut header, name, &contents[..]).unwrap();
+            }
+            tar.into_inner().unwrap().flush().unwrap();
+        }
+    }
+}
+
+/// A pattern and its mode, including patterns which can match the empty string.
+fn random_pattern(rng: &mut Rng) -> (String, &'static str) {
+    let word = *rng.choice(&WORDS[..WORDS.len() - 2]).unwrap();
+    match rng.usize(0..8) {
+        0 => (format!("(?i){word}"), "regex"),
+        1 => (format!("^{word}"), "regex"),
+        2 => (format!("{word}$"), "regex"),
+        3 => ("a*".to_string(), "regex"),
+        4 => (r"\bn\w+".to_string(), "regex"),
+        5 => ("é|ü".to_string(), "regex"),
+        _ => (word.to_string(), "literal"),
+    }
+}
+
+/// With `large`, one file in the tree is split into chunks when searched.
+async fn check_seed(seed: u64, large: bool) {
+    let mut rng = Rng::with_seed(seed);
+    let dir = TempDir::new().unwrap();
+    random_tree(&mut rng, dir.path(), 0);
+    if large {
+        let contents = large_contents(&mut rng, LARGE_LEN);
+        std::fs::write(dir.path().join("large.txt"), contents).unwrap();
+    }
+    let archive = rng.u8(..) < 64;
+    if archive {
+        random_archive(&mut rng, dir.path());
+    }
+    let (pattern, mode) = random_pattern(&mut rng);
+
+    let mut args = vec![
+        "rustcomb".to_string(),
+        dir.path().display().to_string(),
+        pattern.clone(),
+        "--file-pattern-regex".to_string(),
+        mode.to_string(),
+    ];
+    if rng.bool() {
+        args.push(".txt".to_string());
+    }
+    if archive {
+        args.push("-z".to_string());
+    }
+    if rng.bool() {
+        args.extend(["--mmap".to_string(), "never".to_string()]);
+    }
+    let cli = Cli::parse_from(&args);
+
+    let mut expected: Option<(String, Outcome, Vec<FileMatches>)> = None;
+    for strategy in Strategy::registered(4) {
+        let collect = Collect::default();
+        let outcome = strategy
+            .run(&cli, collect.clone(), CancellationToken::new())
+            .await
+            .unwrap_or_else(|err| panic!("seed {seed}: {} failed: {err}", strategy.name()));
+        let found = collect.take();
+
+        match &expected {
+            None => expected = Some((strategy.name(), outcome, found)),
+            Some((name, expected_outcome, expected_found)) => {
+                assert_eq!(
+                    (outcome.matched, outcome.errors),
+                    (expected_outcome.matched, expected_outcome.errors),
+                    "seed {seed}, pattern {pattern:?}: {} and {name} disagree on the outcome",
+                    strategy.name()
+                );
+                assert_eq!(
+                    &found,
+                    expected_found,
+                    "seed {seed}, pattern {pattern:?}: {} and {name} found different matches",
+                    strategy.name()
+                );
+            }
+        }
+    }
+}
+
+/// Every seed, or just `EQUIVALENCE_SEED`.
+fn seeds(count: u64) -> Vec<u64> {
+    match std::env::var("EQUIVALENCE_SEED") {
+        Ok(seed) => vec![seed.parse().expect("EQUIVALENCE_SEED is a number")],
+        Err(_) => (0..count).collect(),
+    }
+}
+
+#[tokio::test]
+async fn test_strategies_are_equivalent() {
+    for seed in seeds(SEEDS) {
+        check_seed(seed, false).await;
+    }
+}
+
+#[tokio::test]
+async fn test_strategies_are_equivalent_on_large_files() {
+    for seed in seeds(LARGE_SEEDS) {
+        check_seed(seed, true).await;
+    }
+}
@@ -0,0 +1,74 @@
+//! The benchmarks' tree generator, included here as benches don't run their own tests.
+
+#[allow(dead_code)]
+#[path = "../benches/file_generation.rs"]
+mod file_generation;
+
+use std::path::{Path, PathBuf};
+
+use assert_fs::fixture;
+use walkdir::WalkDir;
+
+use file_generation::{FileType, TreeSpec, create_files};
+
+fn listing(root: &Path) -> Vec<(PathBuf, u64)> {
+    let mut files: Vec<(PathBuf, u64)> = WalkDir::new(root)
+        .into_iter()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| {
+            let len = entry.metadata().unwrap().len();
+            (entry.path().strip_prefix(root).unwrap().to_path_buf(), len)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_setup() {
+    let spec = TreeSpec {
+        num_of_files: 5,
+        ..TreeSpec::default()
+    };
+
+    let temp_dir: fixture::TempDir = assert_fs::TempDir::new().unwrap();
+    let p = create_files(&temp_dir, &spec);
+    assert!(
+        p.is_dir(),
+        "This should return the directory where the test files are available"
+    );
+    assert_eq!(listing(p).len(), spec.num_of_files);
+
+    temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_nested_mixed_tree() {
+    let spec = TreeSpec {
+        num_of_files: 40,
+        file_type: FileType::Mixed,
+        depth: 2,
+        fan_out: 3,
+        num_of_binary_files: 4,
+        ignored_dirs: true,
+        seed: 7,
+    };
+    assert_eq!(spec.directories().len(), 1 + 3 + 9);
+
+    let temp_dir: fixture::TempDir = assert_fs::TempDir::new().unwrap();
+    let files = listing(create_files(&temp_dir, &spec));
+    // Every file, the binaries, a tenth again within ignored directories and the `.gitignore`
+    assert_eq!(files.len(), 40 + 4 + 4 + 1);
+    assert!(files.iter().any(|(path, _)| path.starts_with("dir_0_0")));
+    assert!(files.iter().any(|(path, _)| path.starts_with("target")));
+    let sizes: std::collections::HashSet<u64> = files.iter().map(|(_, len)| *len).collect();
+    assert!(sizes.len() > 3, "Files of several sizes");
+
+    // The same seed gives the same tree, another doesn't
+    let again: fixture::TempDir = assert_fs::TempDir::new().unwrap();
+    assert_eq!(listing(create_files(&again, &spec)), files);
+    let other: fixture::TempDir = assert_fs::TempDir::new().unwrap();
+    let other_spec = TreeSpec { seed: 8, ..spec };
+    assert_ne!(listing(create_files(&other, &other_spec)), files);
+}